@@ -0,0 +1,3891 @@
+//! Recursively searches through files for literal or regex patterns.
+//!
+//! ```
+//! extern crate content_search;
+//! use content_search::{perform_search, SearchOptions};
+//!
+//! let search_options = SearchOptions {
+//!     directories:vec![String::from("src")],
+//!     file_extensions:vec![String::from(".rs")],
+//!     patterns:vec![String::from("TODO"), String::from("FIXME")],
+//!     ..SearchOptions::default()
+//! };
+//!
+//! let search_results = perform_search(&search_options).unwrap();
+//!
+//! for matched_file in &search_results.matched_files {
+//!     println!("{} matched {:?}", matched_file.file_path, matched_file.matched_patterns);
+//! }
+//! ```
+
+extern crate glob;
+use glob::{glob, Pattern};
+
+extern crate ignore;
+use ignore::WalkBuilder;
+
+extern crate walkdir;
+use walkdir::WalkDir;
+
+extern crate aho_corasick;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+
+extern crate regex;
+use regex::bytes::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+extern crate serde;
+extern crate serde_json;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate encoding_rs;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+extern crate flate2;
+use flate2::read::GzDecoder;
+
+extern crate memmap2;
+use memmap2::Mmap;
+
+extern crate zip;
+use zip::ZipArchive;
+
+extern crate indicatif;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+#[macro_use]
+extern crate log;
+
+extern crate rayon;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::fs;
+use std::str;
+use std::process::Command;
+use std::thread;
+use std::fmt;
+
+/// The parameters of a search, see perform_search. Can be deserialized, e.g. from a config file, any missing field keeps its default.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchOptions {
+    /// The directories to recursively search through.
+    pub directories:Vec<String>,
+
+    /// Consider exactly these files rather than walking the directories, e.g. paths piped in from find.
+    #[serde(skip)]
+    pub input_files:Option<Vec<String>>,
+
+    /// Only queue files with one of these extensions, any extension if empty.
+    pub file_extensions:Vec<String>,
+
+    /// Do not queue files with one of these extensions, takes precedence over file_extensions.
+    pub excluded_extensions:Vec<String>,
+
+    /// Do not queue files with one of the BINARY_EXTENSIONS, unless file_extensions asks for that extension. Archives that search_zip
+    /// or decompress_gzip look inside of aren't affected.
+    pub skip_binary_extensions:bool,
+
+    /// Never descend into directories with one of these names, wherever they are below the roots.
+    pub excluded_directories:Vec<String>,
+
+    /// Do not queue files whose path matches one of these globs.
+    pub exclude_globs:Vec<String>,
+
+    /// Do not queue these exact files, given as absolute paths or relative to the working directory. Both these and the candidates are
+    /// canonicalized before being compared, a path that can't be is compared as it was given.
+    pub excluded_paths:Vec<String>,
+
+    /// The patterns to search for.
+    pub patterns:Vec<String>,
+
+    /// More patterns to search for, which are always matched literally, even in regex_mode.
+    pub literal_patterns:Vec<String>,
+
+    /// Byte sequences to search for, given as hex strings, e.g. 7f454c46 for the start of an ELF file, see decode_hex_pattern. They're always
+    /// matched literally, even in regex_mode, and are reported in their hex form.
+    pub hex_patterns:Vec<String>,
+
+    /// Outside of regex_mode, a pattern that starts with ^ only matches at the start of a file, and one that ends with $ only at its end.
+    /// Doesn't apply to literal_patterns.
+    pub anchor_patterns:bool,
+
+    /// Do not queue files larger than this size in bytes, no limit if 0.
+    pub max_file_size:u64,
+
+    /// Do not queue files smaller than this size in bytes, no limit if 0.
+    pub min_file_size:u64,
+
+    /// Skip files holding a line longer than this many bytes, such as minified sources, no limit if 0. Files are checked as they're read,
+    /// and a file whose search ends early, e.g. with stop_at_first, is still read through to the end for it.
+    pub max_line_length:usize,
+
+    /// Do not queue files last modified more than this many seconds ago, no limit if None.
+    pub max_age_seconds:Option<u64>,
+
+    /// Maximum amount of files to queue, no limit if 0.
+    pub max_files:usize,
+
+    /// Stop searching once this many bytes were read across all files, no limit if 0. The files left by then are skipped.
+    pub max_total_bytes:u64,
+
+    /// Stop searching once this many files matched, no limit if 0. The files left by then are skipped, and since the files are searched in parallel,
+    /// which ones matched first depends on timing, and a few more than this many can end up matched.
+    pub max_matched_files:usize,
+
+    /// Once queuing is done, drop this percentage of the queued files, the largest ones, so that a few huge files can't dominate the search. Disabled if 0.
+    pub skip_largest_percent:f64,
+
+    /// Treat the patterns as regular expressions rather than literal strings.
+    pub regex_mode:bool,
+
+    /// Match the patterns case-insensitively.
+    pub case_insensitive:bool,
+
+    /// Maximum amount of threads to search with, one per logical core if 0.
+    pub max_threads:usize,
+
+    /// How many threads read files into memory for the worker_threads to match, rather than each thread both reading and matching its files.
+    /// This keeps a slow disk busy while the matching happens. If 0, one reader is used once worker_threads is set, and none otherwise.
+    /// Setting either of the two ignores max_threads.
+    pub reader_threads:usize,
+
+    /// How many threads match the files that the reader_threads read. If 0, one per logical core is used once reader_threads is set, and
+    /// files are read and matched by the same max_threads threads otherwise.
+    pub worker_threads:usize,
+
+    /// How many times to retry opening or reading a file that failed, e.g. because another process had it locked, before skipping it.
+    pub retry_count:usize,
+
+    /// Skip files that look binary.
+    pub text_only:bool,
+
+    /// Honor ignore files when queuing files.
+    pub respect_ignore:bool,
+
+    /// Only queue files at most this many directories below each root, no limit if None.
+    pub max_depth:Option<usize>,
+
+    /// Queue hidden files, whose name or any directory they're in starts with a dot, rather than skipping them.
+    pub include_hidden:bool,
+
+    /// Follow symlinks rather than skipping them.
+    pub follow_symlinks:bool,
+
+    /// Only queue files that git reports as changed since this ref, e.g. main or HEAD~3, which requires the directories to be inside git repositories.
+    pub changed_since:Option<String>,
+
+    /// Only queue the files the walk finds that are in this set of canonical paths, e.g. the files that changed since the last search. Every other filter
+    /// still applies, and files outside of the set are left out of the results entirely rather than skipped.
+    #[serde(skip)]
+    pub only_files:Option<HashSet<PathBuf>>,
+
+    /// Give up on matching a file after this many milliseconds and skip it, no limit if 0. The time is checked between the chunks of a streamed
+    /// file, before each regex's pass over it, and between the matches found, even those -word rejects, though a single pass isn't cut short.
+    /// Literal matching is linear in the file's size and rarely needs this, while regexes on pathological files might.
+    pub match_timeout_ms:u64,
+
+    /// Only consider a file matched if every pattern matched, rather than any.
+    pub require_all_patterns:bool,
+
+    /// Only count matches that aren't surrounded by word characters.
+    pub whole_words:bool,
+
+    /// Stop searching a file once this many matches were found in it, no limit if 0.
+    pub max_matches:usize,
+
+    /// Count every occurrence of every pattern, including those that overlap, e.g. aa occurs 3 times in aaaa rather than 2. Only changes the
+    /// counts and locations, a file matches either way. A regex match is then looked for at every offset past the start of the previous one.
+    pub overlapping:bool,
+
+    /// Only consider a file matched if it contains at least this many matches in total, counting repeated matches of the same pattern. With
+    /// require_all_patterns, both have to hold. Reaching max_matches still counts as matched, as the rest of the file is unknown.
+    pub min_matches:usize,
+
+    /// Stop searching a file as soon as it's known to match, so only the first match, or with require_all_patterns the first match of every pattern, is reported.
+    pub stop_at_first:bool,
+
+    /// Only find out which patterns each file contains, for when the matches themselves aren't reported. Only the first match of every pattern is kept,
+    /// and a file is done once every pattern was found, which makes match_count and match_locations incomplete. Has no effect with min_matches,
+    /// which needs every match counted.
+    pub presence_only:bool,
+
+    /// Capture a short snippet around the first match in every matched file, see MatchedFile::first_match_sample.
+    pub capture_sample:bool,
+
+    /// Score every matched file by its matches, see MatchedFile::score. Needs every match counted, so it has no effect with presence_only.
+    pub score_matches:bool,
+
+    /// How much a single match of each pattern adds to the score, keyed by the pattern as it's reported. Patterns left out weigh 1.
+    pub pattern_weights:HashMap<String, u64>,
+
+    /// Treat every file as records separated by NUL bytes rather than as lines, e.g. the output of find -print0. Files holding NUL bytes
+    /// aren't skipped as binary, and every MatchedFile lists its matched_records. With require_all_patterns, a record has to contain every
+    /// pattern on its own. Has no effect on presence_only, which would drop the matches in later records.
+    pub null_data:bool,
+
+    /// Transparently decompress files ending in .gz, their decompressed size is used for the size filters.
+    pub decompress_gzip:bool,
+
+    /// Search every file inside archives ending in .zip rather than the archives themselves, each reported as archive.zip::entry/path. The extension
+    /// and size filters apply to the entries and their decompressed sizes, while an excluded extension of .zip still leaves the archives out.
+    /// Entries are decompressed into memory as a whole, and neither memory_map nor decompress_gzip applies to them.
+    pub search_zip:bool,
+
+    /// Report every searched file under its canonical path, i.e. absolute with every symlink and . or .. resolved, whatever form the
+    /// directories were given in. An archive entry has its archive's path canonicalized. A path that can't be is reported as it was queued,
+    /// and noted as a fallback.
+    pub canonical_paths:bool,
+
+    /// Memory map files and match them in place rather than reading them, only applies to raw bytes, i.e. not gzipped or UTF-16 files.
+    pub memory_map:bool,
+
+    /// How file contents are decoded before matching, raw bytes if Utf8.
+    pub encoding:FileEncoding,
+
+    /// Whether the patterns are matched against the contents of each file, its path below the directory it was found in, or both.
+    pub match_target:MatchTarget,
+
+    /// Where to keep the outcome of every searched file between runs, as JSON. A file whose modification time and size haven't changed
+    /// since it was cached isn't read again. The whole cache is discarded when any option that affects matching has changed.
+    pub cache_path:Option<String>,
+
+    /// Draw progress bars on stderr while queuing and searching, they're hidden regardless when stderr isn't a terminal.
+    #[serde(skip)]
+    pub show_progress:bool,
+
+    /// The least amount of milliseconds between two updates of the progress bars, 0 updates them after every file.
+    pub progress_interval_ms:u64
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            directories:vec![String::from(".")],
+            input_files:None,
+            file_extensions:Vec::new(),
+            excluded_extensions:Vec::new(),
+            skip_binary_extensions:true,
+            excluded_directories:Vec::new(),
+            exclude_globs:Vec::new(),
+            excluded_paths:Vec::new(),
+            patterns:Vec::new(),
+            literal_patterns:Vec::new(),
+            hex_patterns:Vec::new(),
+            anchor_patterns:false,
+            max_file_size:0,
+            min_file_size:0,
+            max_line_length:0,
+            max_age_seconds:None,
+            max_files:0,
+            max_total_bytes:0,
+            max_matched_files:0,
+            skip_largest_percent:0.0,
+            regex_mode:false,
+            case_insensitive:false,
+            max_threads:0,
+            reader_threads:0,
+            worker_threads:0,
+            retry_count:0,
+            text_only:false,
+            respect_ignore:false,
+            max_depth:None,
+            include_hidden:false,
+            follow_symlinks:false,
+            changed_since:None,
+            only_files:None,
+            match_timeout_ms:0,
+            require_all_patterns:false,
+            whole_words:false,
+            max_matches:0,
+            overlapping:false,
+            min_matches:0,
+            stop_at_first:false,
+            presence_only:false,
+            capture_sample:false,
+            score_matches:false,
+            pattern_weights:HashMap::new(),
+            null_data:false,
+            decompress_gzip:false,
+            search_zip:false,
+            canonical_paths:false,
+            memory_map:false,
+            encoding:FileEncoding::Utf8,
+            match_target:MatchTarget::Contents,
+            cache_path:None,
+            show_progress:true,
+            progress_interval_ms:100
+        }
+    }
+}
+
+/// The encoding of the searched files.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    /// Match against the raw bytes of each file.
+    Utf8,
+
+    /// Decode each file from little-endian UTF-16 before matching.
+    Utf16Le,
+
+    /// Decode each file from big-endian UTF-16 before matching.
+    Utf16Be,
+
+    /// Detect each file's encoding from its start, matching raw bytes when it can't be told confidently.
+    Auto
+}
+
+/// What the patterns are matched against.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchTarget {
+    /// Match against the contents of each file.
+    Contents,
+
+    /// Match against the path of each file below the directory it was found in, without reading the file.
+    Paths,
+
+    /// Match against both, a file matches if either does. With require_all_patterns, the patterns can be spread across the two.
+    PathsAndContents
+}
+
+/// Decodes a hex pattern, e.g. 7f454c46, into the bytes it stands for. Both cases are accepted, but not separators or a 0x prefix.
+///
+/// ```
+/// use content_search::decode_hex_pattern;
+///
+/// assert_eq!(decode_hex_pattern("DEADbeef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+/// assert!(decode_hex_pattern("abc").is_err());
+/// assert!(decode_hex_pattern("zz").is_err());
+/// ```
+pub fn decode_hex_pattern(hex_pattern:&str) -> Result<Vec<u8>, String> {
+    if hex_pattern.is_empty() || !hex_pattern.len().is_multiple_of(2) {
+        return Err(format!("The provided hex pattern ({}) is malformed, it needs two hex digits per byte, e.g. 7f454c46", hex_pattern));
+    }
+
+    hex_pattern.as_bytes().chunks(2).map(|hex_digits| {
+        match str::from_utf8(hex_digits).ok().and_then(|hex_digits| u8::from_str_radix(hex_digits, 16).ok()) {
+            Some(pattern_byte) if hex_digits.iter().all(u8::is_ascii_hexdigit) => Ok(pattern_byte),
+            _ => Err(format!("The provided hex pattern ({}) is malformed, {} isn't a hex byte", hex_pattern, String::from_utf8_lossy(hex_digits)))
+        }
+    }).collect()
+}
+
+/// Parses a size in bytes, optionally followed by a K, M or G suffix, which are powers of 1024.
+///
+/// ```
+/// use content_search::parse_size;
+///
+/// assert_eq!(parse_size("1024"), Ok(1024));
+/// assert_eq!(parse_size("1K"), Ok(1024));
+/// assert_eq!(parse_size("1.5M"), Ok(1572864));
+/// assert!(parse_size("10X").is_err());
+/// ```
+pub fn parse_size(size:&str) -> Result<u64, String> {
+    let size:&str = size.trim();
+
+    let (number, multiplier) = match size.chars().last().map(|suffix| suffix.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 1024),
+        Some('M') => (&size[..size.len() - 1], 1024 * 1024),
+        Some('G') => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1)
+    };
+
+    let invalid_size = || format!("Invalid size ({}), expected a number of bytes optionally followed by K, M or G, e.g. 10K or 1.5M", size);
+
+    // Whole numbers are parsed exactly, fractional ones like 1.5M go through a float.
+    match number.parse::<u64>() {
+        Ok(number) => number.checked_mul(multiplier).ok_or_else(invalid_size),
+        Err(_) => match number.parse::<f64>() {
+            Ok(number) if number.is_finite() && number >= 0.0 && number * (multiplier as f64) < u64::MAX as f64 => Ok((number * multiplier as f64) as u64),
+            _ => Err(invalid_size())
+        }
+    }
+}
+
+/// A queued file that matched one or more of the provided patterns.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchedFile {
+    /// The absolute path of the matched file.
+    pub file_path:String,
+
+    /// The list of patterns that matched.
+    pub matched_patterns:Vec<String>,
+
+    /// Where in the file every match occurred.
+    pub match_locations:Vec<MatchLocation>,
+
+    /// The total amount of matches in the file, counting repeated matches of the same pattern.
+    pub match_count:usize,
+
+    /// Whether matching stopped early because max_matches was reached, in which case the file may contain more matches.
+    pub match_cap_reached:bool,
+
+    /// The size of the file in bytes, as it was when queued.
+    pub file_size:u64,
+
+    /// When the file was last modified in seconds since the Unix epoch, None if the platform doesn't report it.
+    pub modified_seconds:Option<u64>,
+
+    /// With capture_sample, the first match along with a few bytes on either side of it from the same line, lossily decoded, with control characters replaced by spaces.
+    pub first_match_sample:Option<String>,
+
+    /// With null_data, the 0-based indices of the NUL separated records that matched, in ascending order.
+    pub matched_records:Option<Vec<usize>>,
+
+    /// With score_matches, the sum of every pattern's match count multiplied by its weight, a pattern weighing 1 unless pattern_weights says otherwise.
+    pub score:Option<u64>
+}
+
+/// A single match within a matched file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchLocation {
+    /// The pattern that matched at this location.
+    pub pattern:String,
+
+    /// The offset in bytes from the start of the file where the match begins.
+    pub byte_offset:usize,
+
+    /// The length of the match in bytes.
+    pub match_length:usize,
+
+    /// The 1-based line number of the match, None if the file was detected as binary or searched with null_data.
+    /// Also None for a match within the file's path, with MatchTarget::Paths or PathsAndContents, in which case byte_offset is within the path.
+    pub line_number:Option<usize>
+}
+
+/// The version of the layout that MatchedFile, SkippedFile and the reports built from them serialize into, e.g. as schema_version in the
+/// -json report. It's bumped whenever a field is renamed, removed or changes type, while added fields leave it as is.
+///
+/// - 1: The layout before the version was reported, where every skip_reason was a string.
+/// - 2: skip_reason is an object with a category, along with details for some categories, and every skipped file has a skip_phase.
+///
+/// ```
+/// extern crate content_search;
+/// extern crate serde_json;
+/// use content_search::{SkipPhase, SkipReason, SkippedFile, JSON_SCHEMA_VERSION};
+///
+/// assert_eq!(JSON_SCHEMA_VERSION, 2);
+///
+/// let skipped_file = SkippedFile { file_path:String::from("/tmp/a.bin"), skip_reason:SkipReason::BinaryDetected, skip_phase:SkipPhase::Queuing };
+/// let skipped_json = serde_json::to_value(&skipped_file).unwrap();
+///
+/// assert_eq!(skipped_json["skip_reason"]["category"], "binary_detected");
+/// assert_eq!(skipped_json["skip_phase"], "queuing");
+/// ```
+pub const JSON_SCHEMA_VERSION:u32 = 2;
+
+/// A file that was not searched, along with the reason why.
+#[derive(Serialize)]
+pub struct SkippedFile {
+    /// Absolute path of the file that was skipped, can be "Unknown".
+    pub file_path:String,
+
+    /// The reason that the file was skipped.
+    pub skip_reason:SkipReason,
+
+    /// When the file was skipped, which is what the skipped files are grouped by before they're sorted by path.
+    pub skip_phase:SkipPhase
+}
+
+/// The phase of a run in which a file was skipped, in the order the phases happen in.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipPhase {
+    /// While queuing, by one of the filters, see queue_files. The file was never opened, except maybe to sample it.
+    Queuing,
+
+    /// While searching, e.g. because the file couldn't be read, see perform_search.
+    Searching,
+
+    /// While working out replacements, see plan_replacements.
+    Replacing
+}
+
+/// Why a file was skipped, see SkippedFile. Displays as a human-readable description, and serializes into its category in snake_case,
+/// along with its details if it has any, e.g. {"category":"too_large","details":{"size":2048,"limit":1024}}.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(tag = "category", content = "details", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The file looked binary, with text_only, or when replacing.
+    BinaryDetected,
+
+    /// The file was larger than max_file_size.
+    TooLarge { size:u64, limit:u64 },
+
+    /// The file was smaller than min_file_size.
+    TooSmall { size:u64, limit:u64 },
+
+    /// A line in the file was longer than max_line_length, which is reached while reading it, so the line's full length isn't known.
+    LineTooLong { limit:usize },
+
+    /// The file was last modified longer ago than max_age_seconds.
+    TooOld { age_seconds:u64, limit_seconds:u64 },
+
+    /// The file was among the largest skip_largest_percent of the queued files, cutoff being the size of the smallest of those.
+    AmongLargest { percent:f64, size:u64, cutoff:u64 },
+
+    /// The file didn't have any of the file_extensions.
+    ExtensionMismatch,
+
+    /// The file had one of the excluded_extensions.
+    ExtensionExcluded,
+
+    /// The file had one of the BINARY_EXTENSIONS, with skip_binary_extensions.
+    BinaryExtension,
+
+    /// The file's path matched one of the exclude_globs.
+    ExcludedByGlob,
+
+    /// The file was one of the excluded_paths.
+    ExcludedPath,
+
+    /// The file, or a directory it's in, is hidden. A hidden directory is skipped as a whole, and its contents aren't listed.
+    Hidden { is_directory:bool },
+
+    /// The file is a symlink, which follow_symlinks wasn't set to follow.
+    SymlinkNotFollowed,
+
+    /// The file hasn't changed since the changed_since ref.
+    Unchanged { git_ref:String },
+
+    /// The file's path isn't valid UTF-8.
+    InvalidPath,
+
+    /// The max_total_bytes budget had been read by the time the file's turn came.
+    BudgetExhausted,
+
+    /// max_matched_files files had matched by the time the file's turn came.
+    EnoughMatched,
+
+    /// Matching the file took longer than match_timeout_ms.
+    MatchTimeout { limit_ms:u64 },
+
+    /// The file couldn't be accessed, opened, read or decompressed, described along with the underlying error.
+    IoError(String),
+
+    /// The file couldn't be decoded with its encoding, described along with the encoding.
+    DecodeError(String),
+
+    /// The file was still searched, just not the way the options asked for, see SearchResults::search_fallbacks.
+    Fallback(String),
+
+    /// When replacing, the file didn't match anymore by the time it was read again.
+    NoLongerMatches
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, formatter:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::BinaryDetected => write!(formatter, "Detected as binary"),
+            SkipReason::TooLarge { size, limit } => write!(formatter, "The file exceeded the provided size ({} > {})", size, limit),
+            SkipReason::TooSmall { size, limit } => write!(formatter, "The file was below the provided minimum size ({} < {})", size, limit),
+            SkipReason::LineTooLong { limit } => write!(formatter, "Line too long (likely minified), longer than {} bytes", limit),
+            SkipReason::TooOld { age_seconds, limit_seconds } => write!(formatter, "The file was last modified too long ago ({}s > {}s)", age_seconds, limit_seconds),
+            SkipReason::AmongLargest { percent, size, cutoff } => write!(formatter, "Among the largest {}% of queued files ({} bytes, the cutoff was {} bytes)", percent, size, cutoff),
+            SkipReason::ExtensionMismatch => write!(formatter, "The file did not have any of the provided extensions."),
+            SkipReason::ExtensionExcluded => write!(formatter, "Extension excluded"),
+            SkipReason::BinaryExtension => write!(formatter, "Binary extension"),
+            SkipReason::ExcludedByGlob => write!(formatter, "Excluded by glob"),
+            SkipReason::ExcludedPath => write!(formatter, "Listed in the exclude file"),
+            SkipReason::Hidden { is_directory:true } => write!(formatter, "Hidden directory"),
+            SkipReason::Hidden { is_directory:false } => write!(formatter, "Hidden file"),
+            SkipReason::SymlinkNotFollowed => write!(formatter, "Symlink (not followed)"),
+            SkipReason::Unchanged { git_ref } => write!(formatter, "Not changed since {}", git_ref),
+            SkipReason::InvalidPath => write!(formatter, "Couldn't convert the PathBuf into a string to get the absolute file path, presumably because the path is invalid UTF-8."),
+            SkipReason::BudgetExhausted => write!(formatter, "Byte budget exhausted"),
+            SkipReason::EnoughMatched => write!(formatter, "Enough files matched"),
+            SkipReason::MatchTimeout { limit_ms } => write!(formatter, "Match timeout ({} ms)", limit_ms),
+            SkipReason::IoError(description) | SkipReason::DecodeError(description) | SkipReason::Fallback(description) => write!(formatter, "{}", description),
+            SkipReason::NoLongerMatches => write!(formatter, "No longer matches, not replacing")
+        }
+    }
+}
+
+/// Every file that perform_search came across, sorted into buckets.
+#[derive(Serialize)]
+pub struct SearchResults {
+    /// Files that met the provided conditions, and matched one or more provided patterns.
+    pub matched_files:Vec<MatchedFile>,
+
+    /// Files that were skipped for some reason.
+    pub skipped_files:Vec<SkippedFile>,
+    
+    /// Candidate files that met the provided conditions, but didn't match any of the provided patterns.
+    pub unmatched_files:Vec<String>,
+
+    /// The total amount of bytes read from the searched files.
+    pub bytes_read:u64,
+
+    /// How long the search took, in seconds.
+    pub elapsed_seconds:f64,
+
+    /// How many times each pattern matched across every matched file, including patterns that never matched.
+    pub pattern_tally:HashMap<String, usize>,
+
+    /// Files that were searched, but not the way the options asked for, e.g. read normally despite memory_map, along with why.
+    pub search_fallbacks:Vec<SkippedFile>
+}
+
+/// A file that passed the queue filters, along with the metadata captured while queuing it.
+pub struct QueuedFile {
+    /// The absolute path of the file.
+    pub file_path:String,
+
+    /// The size of the file in bytes, the decompressed size for gzipped files with decompress_gzip.
+    pub file_size:u64,
+
+    /// When the file was last modified, None if the platform doesn't report it. For an archive entry, when the archive was.
+    pub modified_time:Option<SystemTime>,
+
+    /// With search_zip, the archive and entry that the file is, in which case file_path is what it's reported as.
+    pub archive_entry:Option<ArchiveEntry>
+}
+
+/// A file inside of a zip archive, see SearchOptions::search_zip.
+pub struct ArchiveEntry {
+    /// The absolute path of the archive.
+    pub archive_path:String,
+
+    /// The entry's path within the archive.
+    pub entry_name:String
+}
+
+/// The files that passed the queue filters, along with the ones that didn't, see queue_files.
+pub struct FileQueue {
+    /// The files that would be searched, in the order they were found.
+    pub queued_files:Vec<QueuedFile>,
+
+    /// Files that didn't pass the filters.
+    pub skipped_files:Vec<SkippedFile>
+}
+
+/// The outcome of searching through a single queued file.
+pub enum SearchOutcome {
+    /// The file matched, see MatchedFile.
+    Matched(MatchedFile),
+
+    /// The absolute path of a file that was searched, but didn't match.
+    Unmatched(String),
+
+    /// The file couldn't be searched, e.g. because it couldn't be read or decoded.
+    Skipped(SkippedFile)
+}
+
+// The engine used to match the provided patterns against file contents.
+enum PatternMatcher {
+    // Literal substring matching, the default.
+    Literal(Box<LiteralMatcher>),
+
+    // Regular expression matching, enabled with -re. The set finds which patterns matched, the individual regexes find where.
+    Regex(RegexSet, Vec<Regex>)
+}
+
+/// Every pattern that's searched for, with the literal patterns after the others, and the hex patterns last. A pattern's position in this order is
+/// its index, which is what the matcher reports its matches under.
+pub fn search_patterns(search_options:&SearchOptions) -> impl Iterator<Item = &String> {
+    search_options.patterns.iter().chain(search_options.literal_patterns.iter()).chain(search_options.hex_patterns.iter())
+}
+
+// The pattern that a pattern index refers to, see search_patterns.
+fn pattern_at(search_options:&SearchOptions, pattern_index:usize) -> &String {
+    let literal_index:usize = pattern_index.saturating_sub(search_options.patterns.len());
+
+    match search_options.patterns.get(pattern_index) {
+        Some(pattern) => pattern,
+        None => match search_options.literal_patterns.get(literal_index) {
+            Some(literal_pattern) => literal_pattern,
+            None => &search_options.hex_patterns[literal_index - search_options.literal_patterns.len()]
+        }
+    }
+}
+
+fn pattern_count(search_options:&SearchOptions) -> usize {
+    search_options.patterns.len() + search_options.literal_patterns.len() + search_options.hex_patterns.len()
+}
+
+// A pattern that's anchored to the start or end of the file with -anchor, or both, in which case it has to be the whole file.
+struct AnchoredPattern {
+    pattern_index:usize,
+    pattern_bytes:Vec<u8>,
+    at_start:bool,
+    at_end:bool
+}
+
+// Anchored patterns are compared against the file's ends directly rather than searched for, so that a match elsewhere in the file
+// can never hide one of the other patterns' matches from the automaton.
+struct LiteralMatcher {
+    aho_corasick_search_alg:AhoCorasick,
+
+    // The pattern index that each of the automaton's patterns stands for.
+    pattern_indices:Vec<usize>,
+
+    anchored_patterns:Vec<AnchoredPattern>,
+    case_insensitive:bool
+}
+
+impl AnchoredPattern {
+    fn matches_at(&self, window:&[u8], match_start:usize, case_insensitive:bool) -> bool {
+        match window.get(match_start..match_start + self.pattern_bytes.len()) {
+            Some(window_bytes) if case_insensitive => window_bytes.eq_ignore_ascii_case(&self.pattern_bytes),
+            Some(window_bytes) => window_bytes == self.pattern_bytes.as_slice(),
+            None => false
+        }
+    }
+}
+
+// Only the first anchorable_count patterns can be anchored, the rest are the literal patterns. In regex_mode, every pattern has to be valid UTF-8.
+fn build_pattern_matcher(patterns:&[Vec<u8>], anchorable_count:usize, regex_mode:&bool, case_insensitive:&bool) -> Result<PatternMatcher, String> {
+    if !*regex_mode {
+        let mut automaton_patterns:Vec<&[u8]> = Vec::new();
+        let mut pattern_indices:Vec<usize> = Vec::new();
+        let mut anchored_patterns:Vec<AnchoredPattern> = Vec::new();
+
+        for (pattern_index, pattern) in patterns.iter().enumerate() {
+            let at_start:bool = pattern_index < anchorable_count && pattern.starts_with(b"^");
+            let unanchored_start:&[u8] = if at_start { &pattern[1..] } else { pattern };
+
+            let at_end:bool = pattern_index < anchorable_count && unanchored_start.ends_with(b"$");
+            let pattern_bytes:&[u8] = if at_end { &unanchored_start[..unanchored_start.len() - 1] } else { unanchored_start };
+
+            // A lone ^ or $ has nothing to anchor, so it's searched for as it is.
+            if (at_start || at_end) && !pattern_bytes.is_empty() {
+                anchored_patterns.push(AnchoredPattern { pattern_index, pattern_bytes:pattern_bytes.to_vec(), at_start, at_end });
+            } else {
+                automaton_patterns.push(pattern);
+                pattern_indices.push(pattern_index);
+            }
+        }
+
+        let aho_corasick_search_alg:AhoCorasick = AhoCorasickBuilder::new().ascii_case_insensitive(*case_insensitive).build(automaton_patterns);
+        return Ok(PatternMatcher::Literal(Box::new(LiteralMatcher { aho_corasick_search_alg, pattern_indices, anchored_patterns, case_insensitive:*case_insensitive })));
+    }
+
+    let patterns:Vec<String> = patterns.iter().map(|pattern| String::from_utf8_lossy(pattern).into_owned()).collect();
+    let mut regexes:Vec<Regex> = Vec::new();
+
+    // Compile every pattern on its own first, so that an error can name the offending pattern.
+    for pattern in &patterns {
+        match RegexBuilder::new(pattern).case_insensitive(*case_insensitive).build() {
+            Ok(regex) => regexes.push(regex),
+            Err(error) => return Err(format!("The provided pattern ({}) is not a valid regular expression, error: {}", pattern, error))
+        };
+    }
+
+    match RegexSetBuilder::new(&patterns).case_insensitive(*case_insensitive).build() {
+        Ok(regex_set) => Ok(PatternMatcher::Regex(regex_set, regexes)),
+        Err(error) => Err(format!("Couldn't compile the provided patterns into a regex set, error: {}", error))
+    }
+}
+
+// The matcher for every pattern of the provided options, see search_patterns.
+fn build_search_matcher(search_options:&SearchOptions) -> Result<PatternMatcher, String> {
+    // In regex mode, the literal patterns are escaped so that their special characters match themselves.
+    let mut matched_patterns:Vec<Vec<u8>> = search_options.patterns.iter().cloned().chain(search_options.literal_patterns.iter().map(|literal_pattern| {
+        if search_options.regex_mode { regex::escape(literal_pattern) } else { literal_pattern.clone() }
+    })).map(String::into_bytes).collect();
+
+    // The hex patterns are matched as raw bytes, which regexes spell out byte by byte outside of Unicode mode, so that they never need to be valid UTF-8.
+    for hex_pattern in &search_options.hex_patterns {
+        let pattern_bytes:Vec<u8> = decode_hex_pattern(hex_pattern)?;
+
+        matched_patterns.push(if search_options.regex_mode {
+            format!("(?-u:{})", pattern_bytes.iter().map(|pattern_byte| format!("\\x{:02x}", pattern_byte)).collect::<String>()).into_bytes()
+        } else {
+            pattern_bytes
+        });
+    }
+
+    // Regexes have their own anchors, -anchor only applies to literal matching.
+    let anchorable_count:usize = if search_options.anchor_patterns { search_options.patterns.len() } else { 0 };
+    build_pattern_matcher(&matched_patterns, anchorable_count, &search_options.regex_mode, &search_options.case_insensitive)
+}
+
+// The amount of bytes sampled from the start of a file when checking whether it's binary.
+const BINARY_SAMPLE_SIZE:usize = 8192;
+
+// Reads up to BINARY_SAMPLE_SIZE bytes from the start of the file at the provided path, after decompressing it if it's gzipped.
+fn read_binary_sample(file_path:&Path, is_gzipped:bool, retry_count:usize) -> io::Result<Vec<u8>> {
+    let mut binary_sample:Vec<u8> = Vec::with_capacity(BINARY_SAMPLE_SIZE);
+    open_file_reader(file_path, is_gzipped, retry_count)?.take(BINARY_SAMPLE_SIZE as u64).read_to_end(&mut binary_sample)?;
+    Ok(binary_sample)
+}
+
+// Whether the file should be decompressed before reading, which only happens with -z.
+fn is_gzip_file(file_path:&Path, decompress_gzip:bool) -> bool {
+    decompress_gzip && file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+// Whether the file is an archive whose entries are searched, which only happens with -zip.
+fn is_zip_archive(file_path:&Path, search_zip:bool) -> bool {
+    search_zip && file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+// Separates an archive's path from the path of an entry within it, in the path an entry is reported as.
+const ARCHIVE_ENTRY_SEPARATOR:&str = "::";
+
+// Queues every file entry of the archive as a file of its own, applying the extension filters to the entry's path and the size filters to its
+// decompressed size, as its header states it. An archive that can't be opened is skipped as a whole.
+fn queue_archive_entries(archive_path:&str, modified_time:Option<SystemTime>, search_options:&SearchOptions, queued_files:&mut Vec<QueuedFile>, skipped_files:&mut Vec<SkippedFile>) {
+    let mut zip_archive:ZipArchive<File> = match File::open(archive_path).map_err(|error| error.to_string()).and_then(|archive_file| ZipArchive::new(archive_file).map_err(|error| error.to_string())) {
+        Ok(zip_archive) => zip_archive,
+        Err(error) => {
+            let skipped_file = SkippedFile {
+                file_path:String::from(archive_path),
+                skip_reason:SkipReason::IoError(format!("Failed to open the zip archive @ {}, error: {}", archive_path, error)),
+                skip_phase:SkipPhase::Queuing
+            };
+
+            skipped_files.push(skipped_file);
+            return;
+        }
+    };
+
+    for entry_index in 0..zip_archive.len() {
+        let (entry_name, entry_size) = match zip_archive.by_index_raw(entry_index) {
+            Ok(zip_entry) if zip_entry.is_dir() => continue,
+            Ok(zip_entry) => (String::from(zip_entry.name()), zip_entry.size()),
+            Err(error) => {
+                let skipped_file = SkippedFile {
+                    file_path:String::from(archive_path),
+                    skip_reason:SkipReason::IoError(format!("Failed to read entry #{} of the zip archive @ {}, error: {}", entry_index, archive_path, error)),
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+        };
+
+        let entry_path:String = format!("{}{}{}", archive_path, ARCHIVE_ENTRY_SEPARATOR, entry_name);
+
+        let skip_reason:Option<SkipReason> = if has_any_extension(Path::new(&entry_name), &search_options.excluded_extensions) {
+            Some(SkipReason::ExtensionExcluded)
+        } else if has_binary_extension(Path::new(&entry_name), search_options) {
+            Some(SkipReason::BinaryExtension)
+        } else if !search_options.file_extensions.is_empty() && !has_any_extension(Path::new(&entry_name), &search_options.file_extensions) {
+            Some(SkipReason::ExtensionMismatch)
+        } else if search_options.max_file_size > 0 && entry_size > search_options.max_file_size {
+            Some(SkipReason::TooLarge { size:entry_size, limit:search_options.max_file_size })
+        } else if search_options.min_file_size > 0 && entry_size < search_options.min_file_size {
+            Some(SkipReason::TooSmall { size:entry_size, limit:search_options.min_file_size })
+        } else {
+            None
+        };
+
+        if let Some(skip_reason) = skip_reason {
+            let skipped_file = SkippedFile {
+                file_path:entry_path,
+                skip_reason,
+                skip_phase:SkipPhase::Queuing
+            };
+
+            skipped_files.push(skipped_file);
+            continue;
+        }
+
+        queued_files.push(QueuedFile {
+            file_path:entry_path,
+            file_size:entry_size,
+            modified_time,
+            archive_entry:Some(ArchiveEntry { archive_path:String::from(archive_path), entry_name })
+        });
+    }
+}
+
+// Decompresses the archive entry into memory, and decodes it when the encoding is forced, since there's no file to sniff the encoding of. With
+// text_only, an entry that turns out to be binary is skipped now, as it couldn't be sampled while queuing.
+fn load_archive_entry(queued_entry:&QueuedFile, archive_entry:&ArchiveEntry, search_options:&SearchOptions) -> Result<LoadedFile, SkipReason> {
+    let archive_file:File = retry_io(search_options.retry_count, || File::open(&archive_entry.archive_path)).map_err(|error| {
+        SkipReason::IoError(format!("Failed to open the zip archive @ {} holding {}, error: {:?}", archive_entry.archive_path, archive_entry.entry_name, error))
+    })?;
+
+    let mut zip_archive:ZipArchive<File> = ZipArchive::new(archive_file).map_err(|error| {
+        SkipReason::IoError(format!("Failed to read the zip archive @ {} holding {}, error: {}", archive_entry.archive_path, archive_entry.entry_name, error))
+    })?;
+
+    let mut zip_entry = zip_archive.by_name(&archive_entry.entry_name).map_err(|error| {
+        SkipReason::IoError(format!("Failed to find the entry {} in the zip archive @ {}, error: {}", archive_entry.entry_name, archive_entry.archive_path, error))
+    })?;
+
+    if let Some(encoding) = decoding_for(search_options.encoding) {
+        let (decoded_contents, entry_size) = decode_file(&queued_entry.file_path, zip_entry, encoding)?;
+        return Ok(LoadedFile { file_contents:LoadedContents::Buffered(decoded_contents), bytes_read:entry_size });
+    }
+
+    let mut entry_contents:Vec<u8> = Vec::new();
+
+    if let Err(error) = zip_entry.read_to_end(&mut entry_contents) {
+        return Err(SkipReason::IoError(format!("Failed to decompress the entry {} of the zip archive @ {}, error: {}", archive_entry.entry_name, archive_entry.archive_path, error)));
+    }
+
+    if search_options.text_only && !search_options.null_data && is_binary_sample(&entry_contents[..entry_contents.len().min(BINARY_SAMPLE_SIZE)]) {
+        return Err(SkipReason::BinaryDetected);
+    }
+
+    Ok(LoadedFile { bytes_read:entry_contents.len() as u64, file_contents:LoadedContents::Buffered(entry_contents) })
+}
+
+// How long to wait before the first retry with -retry, doubled for every retry after it.
+const RETRY_BACKOFF:Duration = Duration::from_millis(25);
+
+// Runs the IO operation, retrying it up to retry_count times with a backoff while it fails. A missing file won't show up by waiting, so that's never retried.
+fn retry_io<T, F:FnMut() -> io::Result<T>>(retry_count:usize, mut io_operation:F) -> io::Result<T> {
+    let mut backoff:Duration = RETRY_BACKOFF;
+
+    for retry_number in 1..=retry_count {
+        match io_operation() {
+            Err(ref error) if error.kind() != io::ErrorKind::NotFound => {
+                info!("Retrying in {:?} ({} of {}), error: {}", backoff, retry_number, retry_count, error);
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+
+            io_result => return io_result
+        }
+    }
+
+    io_operation()
+}
+
+// Retries the failed reads of the wrapped reader, so that a transient error partway through a file doesn't get it skipped.
+struct RetryingReader<R:Read> {
+    reader:R,
+    retry_count:usize
+}
+
+impl<R:Read> Read for RetryingReader<R> {
+    fn read(&mut self, buffer:&mut [u8]) -> io::Result<usize> {
+        let reader:&mut R = &mut self.reader;
+        retry_io(self.retry_count, || reader.read(buffer))
+    }
+}
+
+// Opens the file at the provided path, wrapping it in a decoder if it's gzipped. The retries happen beneath the decoder, which never sees an error that a retry fixed.
+fn open_file_reader(file_path:&Path, is_gzipped:bool, retry_count:usize) -> io::Result<Box<dyn Read>> {
+    let file_stream = RetryingReader { reader:retry_io(retry_count, || File::open(file_path))?, retry_count };
+
+    if is_gzipped {
+        Ok(Box::new(GzDecoder::new(file_stream)))
+    } else {
+        Ok(Box::new(file_stream))
+    }
+}
+
+// Gzip stores the decompressed size modulo 2^32 in the last 4 bytes, which is exact for anything under 4GB.
+fn gzip_decompressed_size(file_path:&Path) -> io::Result<u64> {
+    let mut file_stream:File = File::open(file_path)?;
+    let mut size_bytes:[u8; 4] = [0; 4];
+
+    file_stream.seek(SeekFrom::End(-4))?;
+    file_stream.read_exact(&mut size_bytes)?;
+
+    Ok(u32::from_le_bytes(size_bytes) as u64)
+}
+
+// A sample is considered binary if it contains a NUL byte, the same heuristic grep uses.
+fn is_binary_sample(binary_sample:&[u8]) -> bool {
+    binary_sample.contains(&0)
+}
+
+// What the start of a file suggests its encoding is, with -enc auto.
+enum SniffedEncoding {
+    Raw,
+    Decoded(&'static Encoding),
+    Unsure
+}
+
+// Detects the encoding from a sample of the file's start, going by its BOM if it has one. Without one, UTF-16 is recognised by the
+// NUL bytes padding every other byte of mostly ASCII text, and anything else is only trusted to be matched raw if it's valid UTF-8.
+fn sniff_encoding(encoding_sample:&[u8]) -> SniffedEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(encoding_sample) {
+        return if encoding == UTF_8 { SniffedEncoding::Raw } else { SniffedEncoding::Decoded(encoding) };
+    }
+
+    let unit_count:usize = encoding_sample.len() / 2;
+
+    if unit_count > 0 {
+        let even_nuls:usize = encoding_sample.chunks_exact(2).filter(|code_unit| code_unit[0] == 0).count();
+        let odd_nuls:usize = encoding_sample.chunks_exact(2).filter(|code_unit| code_unit[1] == 0).count();
+
+        // At least 90% of the code units have to look like ASCII, and barely any can be two NULs.
+        if odd_nuls * 10 >= unit_count * 9 && even_nuls * 10 < unit_count {
+            return SniffedEncoding::Decoded(UTF_16LE);
+        }
+
+        if even_nuls * 10 >= unit_count * 9 && odd_nuls * 10 < unit_count {
+            return SniffedEncoding::Decoded(UTF_16BE);
+        }
+    }
+
+    // The sample can end partway through a character, which still counts as valid.
+    match str::from_utf8(encoding_sample) {
+        Ok(_) => SniffedEncoding::Raw,
+        Err(error) if error.error_len().is_none() => SniffedEncoding::Raw,
+        Err(_) => SniffedEncoding::Unsure
+    }
+}
+
+// The decoder to read the file with, None if its raw bytes are matched. With -enc auto, the error is why the encoding couldn't be
+// detected, in which case the raw bytes are matched as well.
+fn resolve_decoding(file_path:&Path, file_encoding:FileEncoding, is_gzipped:bool, retry_count:usize) -> Result<Option<&'static Encoding>, String> {
+    if file_encoding != FileEncoding::Auto {
+        return Ok(decoding_for(file_encoding));
+    }
+
+    let encoding_sample:Vec<u8> = match read_binary_sample(file_path, is_gzipped, retry_count) {
+        Ok(encoding_sample) => encoding_sample,
+        Err(error) => return Err(format!("Error when sampling the file for encoding detection, matched raw bytes: {:?}", error))
+    };
+
+    match sniff_encoding(&encoding_sample) {
+        SniffedEncoding::Raw => Ok(None),
+        SniffedEncoding::Decoded(encoding) => Ok(Some(encoding)),
+        SniffedEncoding::Unsure => Err(String::from("Couldn't confidently detect the encoding, matched raw bytes"))
+    }
+}
+
+// Whether the file has any of the provided extensions, shared by -ext and -xext. Extensions are compared case-insensitively, with or
+// without a leading dot, so .rs and rs both match main.rs and MAIN.RS, but not a dotfile named .rs.
+fn has_any_extension<S:AsRef<str>>(file_path:&Path, file_extensions:&[S]) -> bool {
+    let path_extension:&str = match file_path.extension().and_then(|path_extension| path_extension.to_str()) {
+        Some(path_extension) => path_extension,
+        None => return false
+    };
+
+    file_extensions.iter().any(|file_extension| {
+        let file_extension:&str = file_extension.as_ref();
+        let file_extension:&str = file_extension.strip_prefix('.').unwrap_or(file_extension);
+        path_extension.eq_ignore_ascii_case(file_extension)
+    })
+}
+
+/// The extensions of files that are almost always binary, which skip_binary_extensions skips without reading them. Gzip isn't among them,
+/// as decompress_gzip searches inside of it.
+pub static BINARY_EXTENSIONS:&[&str] = &[
+    // Images
+    ".png", ".jpg", ".jpeg", ".gif", ".bmp", ".ico", ".webp", ".tif", ".tiff", ".psd",
+    // Audio and video
+    ".mp3", ".wav", ".flac", ".ogg", ".mp4", ".m4a", ".avi", ".mov", ".mkv", ".webm",
+    // Archives
+    ".zip", ".7z", ".rar", ".tar", ".bz2", ".xz", ".zst", ".jar", ".war", ".iso", ".dmg",
+    // Executables, libraries and object files
+    ".exe", ".dll", ".so", ".dylib", ".o", ".a", ".lib", ".obj", ".class", ".pyc", ".pyo", ".wasm",
+    // Documents
+    ".pdf", ".doc", ".docx", ".xls", ".xlsx", ".ppt", ".pptx",
+    // Fonts
+    ".ttf", ".otf", ".woff", ".woff2", ".eot",
+    // Databases
+    ".db", ".sqlite"
+];
+
+// Whether the file is left out by skip_binary_extensions. Asking for the extension with file_extensions lets it through.
+fn has_binary_extension(file_path:&Path, search_options:&SearchOptions) -> bool {
+    search_options.skip_binary_extensions && has_any_extension(file_path, BINARY_EXTENSIONS) && !has_any_extension(file_path, &search_options.file_extensions)
+}
+
+// The path of the file below the root it was found in, or the whole path if it wasn't found in any of them.
+fn path_below_root<'a>(file_path:&'a Path, directories:&[String]) -> &'a Path {
+    directories.iter().find_map(|directory| file_path.strip_prefix(directory).ok()).unwrap_or(file_path)
+}
+
+// Whether the file or any directory it's in, below the root it was found in, has a name starting with a dot.
+fn is_hidden_path(file_path:&Path, directories:&[String]) -> bool {
+    path_below_root(file_path, directories).components().any(|component| match component {
+        Component::Normal(component_name) => component_name.to_string_lossy().starts_with('.'),
+        _ => false
+    })
+}
+
+// Recursively lists every entry below the provided directory, through glob by default. With -gi, a walker that honors ignore files
+// is used instead, and with -depth or -exclude-dir, a walker that can limit how deep it descends and prune whole subtrees, since glob can't.
+// A depth of 0 is the exception, glob can list a single level just fine.
+fn walk_directory(directory:&String, search_options:&SearchOptions) -> Result<Box<dyn Iterator<Item = Result<PathBuf, String>>>, String> {
+    // A depth of N means files at most N directories below the root, the walkers count the files directly in the root as depth 1.
+    let walker_depth:Option<usize> = search_options.max_depth.map(|max_depth| max_depth + 1);
+
+    // The roots themselves are never pruned, even if their name is excluded.
+    let excluded_directories:Vec<String> = search_options.excluded_directories.clone();
+    let is_excluded_directory = move |file_name:&OsStr, is_dir:bool, depth:usize| -> bool {
+        is_dir && depth > 0 && excluded_directories.iter().any(|excluded_directory| file_name == excluded_directory.as_str())
+    };
+
+    if search_options.respect_ignore {
+        // Hidden files are still included, only ignore rules and the .git directory itself should exclude anything.
+        let directory_walker = WalkBuilder::new(directory).hidden(false).follow_links(search_options.follow_symlinks).max_depth(walker_depth).filter_entry(move |directory_entry| {
+            let is_dir:bool = directory_entry.file_type().is_some_and(|file_type| file_type.is_dir());
+            directory_entry.file_name() != ".git" && !is_excluded_directory(directory_entry.file_name(), is_dir, directory_entry.depth())
+        }).build();
+
+        return Ok(Box::new(directory_walker.map(|element| match element {
+            Ok(directory_entry) => Ok(directory_entry.into_path()),
+            Err(error) => Err(format!("{:?}", error))
+        })));
+    }
+
+    if search_options.max_depth.is_some_and(|max_depth| max_depth > 0) || !search_options.excluded_directories.is_empty() {
+        let directory_walker = WalkDir::new(directory).follow_links(search_options.follow_symlinks).max_depth(walker_depth.unwrap_or(usize::MAX)).into_iter().filter_entry(move |directory_entry| {
+            !is_excluded_directory(directory_entry.file_name(), directory_entry.file_type().is_dir(), directory_entry.depth())
+        });
+
+        return Ok(Box::new(directory_walker.map(|element| match element {
+            Ok(directory_entry) => Ok(directory_entry.into_path()),
+            Err(error) => Err(format!("{:?}", error))
+        })));
+    }
+
+    let glob_suffix:&str = if search_options.max_depth == Some(0) { "*" } else { "**/*" };
+    let glob_pattern:String = if directory.ends_with("/") || directory.ends_with("\\") { directory.clone() + glob_suffix } else { directory.clone() + "/" + glob_suffix };
+
+    match glob(glob_pattern.as_str()) {
+        Ok(directory_entries) => Ok(Box::new(directory_entries.map(|element| element.map_err(|error| format!("{:?}", error))))),
+        Err(error) => Err(format!("Couldn't retrieve directory entries for the directory ({}), error: {:?}", directory, error))
+    }
+}
+
+// The queue's size isn't known until the walk finishes, so it gets a spinner, whereas the search gets a bar with a percentage and an ETA.
+fn build_progress_bar(progress_length:Option<u64>, show_progress:bool) -> ProgressBar {
+    if !show_progress {
+        return ProgressBar::hidden();
+    }
+
+    let (progress_bar, progress_template) = match progress_length {
+        Some(progress_length) => (ProgressBar::new(progress_length), "[{bar:40}] {percent:>3}% {pos} / {len} ETA {eta} {msg}"),
+        None => (ProgressBar::new_spinner(), "{spinner} {msg}")
+    };
+
+    progress_bar.set_draw_target(ProgressDrawTarget::stderr());
+    progress_bar.set_style(ProgressStyle::with_template(progress_template).unwrap_or_else(|_| ProgressStyle::default_bar()).progress_chars("=> "));
+    progress_bar
+}
+
+// Runs git from within the provided directory, returning its standard output.
+fn run_git(directory:&str, git_arguments:&[&str]) -> Result<Vec<u8>, String> {
+    let git_output = match Command::new("git").arg("-C").arg(directory).args(git_arguments).output() {
+        Ok(git_output) => git_output,
+        Err(error) => return Err(format!("Couldn't run git in the directory ({}), error: {}", directory, error))
+    };
+
+    if !git_output.status.success() {
+        return Err(format!("git {} failed in the directory ({}), error: {}", git_arguments.join(" "), directory, String::from_utf8_lossy(&git_output.stderr).trim()));
+    }
+
+    Ok(git_output.stdout)
+}
+
+// The canonical paths of the files that changed since the provided ref, in the repository containing the provided directory. Deleted files are left out, since
+// there's nothing to search in them anyway.
+fn list_changed_files(git_ref:&str, directory:&str) -> Result<HashSet<PathBuf>, String> {
+    let repository_root:Vec<u8> = match run_git(directory, &["rev-parse", "--show-toplevel"]) {
+        Ok(repository_root) => repository_root,
+        Err(error) => return Err(format!("The directory ({}) doesn't seem to be inside a git repository, which -since-commit needs. {}", directory, error))
+    };
+
+    let repository_root:PathBuf = PathBuf::from(String::from_utf8_lossy(&repository_root).trim_end());
+
+    // The paths are relative to the root of the repository, and NUL separated so that git doesn't quote unusual ones.
+    let changed_paths:Vec<u8> = run_git(directory, &["diff", "--name-only", "-z", git_ref, "--"])?;
+
+    Ok(changed_paths.split(|&path_byte| path_byte == 0).filter(|changed_path| !changed_path.is_empty()).filter_map(|changed_path| {
+        fs::canonicalize(repository_root.join(String::from_utf8_lossy(changed_path).as_ref())).ok()
+    }).collect())
+}
+
+// Moves the largest skip_percent percent of the queued files over to the skipped files, using the sizes captured while queuing. Files of the same size
+// are dropped in path order, so that the same files are dropped every time, and the rest of the queue keeps its order.
+fn skip_largest_files(queued_files:&mut Vec<QueuedFile>, skipped_files:&mut Vec<SkippedFile>, skip_percent:f64) {
+    let skip_count:usize = ((queued_files.len() as f64 * skip_percent.min(100.0) / 100.0).round() as usize).min(queued_files.len());
+
+    if skip_count == 0 {
+        return;
+    }
+
+    let mut size_order:Vec<usize> = (0..queued_files.len()).collect();
+    size_order.sort_by(|&previous, &current| {
+        queued_files[current].file_size.cmp(&queued_files[previous].file_size).then_with(|| queued_files[previous].file_path.cmp(&queued_files[current].file_path))
+    });
+
+    let size_threshold:u64 = queued_files[size_order[skip_count - 1]].file_size;
+    let largest_files:HashSet<usize> = size_order.into_iter().take(skip_count).collect();
+
+    let mut file_index:usize = 0;
+
+    queued_files.retain(|queued_file| {
+        let is_largest:bool = largest_files.contains(&file_index);
+        file_index += 1;
+
+        if is_largest {
+            skipped_files.push(SkippedFile {
+                file_path:queued_file.file_path.clone(),
+                skip_reason:SkipReason::AmongLargest { percent:skip_percent, size:queued_file.file_size, cutoff:size_threshold },
+                skip_phase:SkipPhase::Queuing
+            });
+        }
+
+        !is_largest
+    });
+}
+
+/// Queues every file below the provided directories that passes the provided filters, without searching through them.
+pub fn queue_files(search_options:&SearchOptions) -> Result<FileQueue, String> {
+    let queue_start:SystemTime = SystemTime::now();
+
+    let mut exclusion_patterns:Vec<Pattern> = Vec::new();
+
+    for exclude_glob in &search_options.exclude_globs {
+        match Pattern::new(exclude_glob) {
+            Ok(exclusion_pattern) => exclusion_patterns.push(exclusion_pattern),
+            Err(error) => return Err(format!("The provided exclusion glob ({}) is invalid, error: {}", exclude_glob, error))
+        };
+    }
+
+    let excluded_paths:HashSet<PathBuf> = search_options.excluded_paths.iter().map(|excluded_path| {
+        fs::canonicalize(excluded_path).unwrap_or_else(|_| PathBuf::from(excluded_path))
+    }).collect();
+
+    let extensions_matter:bool = !search_options.file_extensions.is_empty();
+    let file_size_matters:bool = search_options.max_file_size > 0;
+    let minimum_size_matters:bool = search_options.min_file_size > 0;
+    let file_count_matters:bool = search_options.max_files > 0;
+
+    // With -since-commit, the files changed since the ref in every directory's repository, which the queued files are intersected with.
+    let changed_files:Option<HashSet<PathBuf>> = match &search_options.changed_since {
+        Some(git_ref) => {
+            let mut changed_files:HashSet<PathBuf> = HashSet::new();
+
+            for directory in &search_options.directories {
+                changed_files.extend(list_changed_files(git_ref, directory)?);
+            }
+
+            Some(changed_files)
+        }
+
+        None => None
+    };
+
+    let mut directory_entries:Vec<Box<dyn Iterator<Item = Result<PathBuf, String>>>> = Vec::new();
+
+    // A list of input files bypasses the traversal entirely, every listed path is still subject to the filters below.
+    match &search_options.input_files {
+        Some(input_files) => directory_entries.push(Box::new(input_files.clone().into_iter().map(|input_file| Ok(PathBuf::from(input_file))))),
+        None => for directory in &search_options.directories {
+            directory_entries.push(walk_directory(directory, search_options)?);
+        }
+    }
+
+    // Overlapping directories, followed symlinks and input lists can yield the same file more than once, so the canonical paths of queued files are remembered.
+    let directories_overlap:bool = search_options.directories.len() > 1 || search_options.follow_symlinks || search_options.input_files.is_some();
+    let mut seen_files:HashSet<PathBuf> = HashSet::new();
+
+    // Directories whose entries are dropped, because they're either symlinks that aren't followed, or were already visited through another path.
+    let mut pruned_directories:Vec<PathBuf> = Vec::new();
+    let mut visited_directories:HashSet<PathBuf> = HashSet::new();
+
+    // List of queued files that will be searched for matching patterns, and the files that didn't pass the filters.
+    let mut queued_files:Vec<QueuedFile> = Vec::new();
+    let mut skipped_files:Vec<SkippedFile> = Vec::new();
+
+    let queue_progress:ProgressBar = build_progress_bar(None, search_options.show_progress);
+    let mut last_progress:Option<Instant> = None;
+
+    // Fill the queue with candidate files.
+    for (index, element) in directory_entries.into_iter().flatten().enumerate() {
+        let path_obj = match element {
+            Ok(file_path) => file_path,
+            Err(error) => {
+                let skipped_file = SkippedFile {
+                    file_path:String::from("Unknown"),
+                    skip_reason:SkipReason::IoError(format!("Skipped due to error when matching element: {}", error)),
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+        };
+
+        if pruned_directories.iter().any(|pruned_directory| path_obj.starts_with(pruned_directory)) {
+            continue;
+        }
+
+        // Hidden directories are pruned, so that their contents don't each show up as skipped.
+        if !search_options.include_hidden && is_hidden_path(&path_obj, &search_options.directories) {
+            let is_directory:bool = path_obj.is_dir();
+
+            if is_directory {
+                pruned_directories.push(path_obj.clone());
+            }
+
+            let skipped_file = SkippedFile {
+                file_path:path_obj.to_string_lossy().into_owned(),
+                skip_reason:SkipReason::Hidden { is_directory },
+                skip_phase:SkipPhase::Queuing
+            };
+
+            skipped_files.push(skipped_file);
+            continue;
+        }
+
+        let symlink_metadata:fs::Metadata = match fs::symlink_metadata(&path_obj) {
+            Ok(symlink_metadata) => symlink_metadata,
+            Err(error) => {
+                let skipped_file = SkippedFile {
+                    file_path:path_obj.to_string_lossy().into_owned(),
+                    skip_reason:SkipReason::IoError(format!("Error when retrieving the file's metadata: {:?}", error)),
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+        };
+
+        let is_symlink:bool = symlink_metadata.file_type().is_symlink();
+
+        // The metadata of what the path points to, which is only different from the symlink metadata when following a symlink.
+        let file_metadata:fs::Metadata = if is_symlink && search_options.follow_symlinks {
+            match fs::metadata(&path_obj) {
+                Ok(file_metadata) => file_metadata,
+                Err(error) => {
+                    let skipped_file = SkippedFile {
+                        file_path:path_obj.to_string_lossy().into_owned(),
+                        skip_reason:SkipReason::IoError(format!("Error when resolving the symlink: {:?}", error)),
+                        skip_phase:SkipPhase::Queuing
+                    };
+
+                    skipped_files.push(skipped_file);
+                    continue;
+                }
+            }
+        } else {
+            symlink_metadata
+        };
+
+        if is_symlink && !search_options.follow_symlinks {
+            if path_obj.is_dir() {
+                pruned_directories.push(path_obj);
+            } else if path_obj.is_file() {
+                let skipped_file = SkippedFile {
+                    file_path:path_obj.to_string_lossy().into_owned(),
+                    skip_reason:SkipReason::SymlinkNotFollowed,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+            }
+
+            continue;
+        }
+
+        // A directory that was already visited means a symlink cycle, or another path to the same directory.
+        if file_metadata.is_dir() && search_options.follow_symlinks {
+            let canonical_path:PathBuf = fs::canonicalize(&path_obj).unwrap_or_else(|_| path_obj.clone());
+
+            if !visited_directories.insert(canonical_path) {
+                pruned_directories.push(path_obj);
+                continue;
+            }
+        }
+
+        // If the path points to a file, continue.
+        if file_metadata.is_file() {
+            let absolute_file_path:String = match path_obj.to_str() {
+                Some(absolute_file_path) => String::from(absolute_file_path),
+                None => {
+                    let skipped_file = SkippedFile {
+                        file_path:String::from("Unknown"),
+                        skip_reason:SkipReason::InvalidPath,
+                        skip_phase:SkipPhase::Queuing
+                    };
+
+                    skipped_files.push(skipped_file);
+                    continue;
+                }
+            };
+
+            if let Some(only_files) = &search_options.only_files {
+                let canonical_path:PathBuf = fs::canonicalize(&path_obj).unwrap_or_else(|_| path_obj.clone());
+
+                if !only_files.contains(&canonical_path) {
+                    continue;
+                }
+            }
+
+            if directories_overlap {
+                let canonical_path:PathBuf = fs::canonicalize(&path_obj).unwrap_or_else(|_| path_obj.clone());
+
+                if !seen_files.insert(canonical_path) {
+                    continue;
+                }
+            }
+
+            if exclusion_patterns.iter().any(|exclusion_pattern| exclusion_pattern.matches_path(&path_obj)) {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::ExcludedByGlob,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            if !excluded_paths.is_empty() && (excluded_paths.contains(&path_obj) || fs::canonicalize(&path_obj).is_ok_and(|canonical_path| excluded_paths.contains(&canonical_path))) {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::ExcludedPath,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            if let (Some(changed_files), Some(git_ref)) = (&changed_files, &search_options.changed_since) {
+                let canonical_path:PathBuf = fs::canonicalize(&path_obj).unwrap_or_else(|_| path_obj.clone());
+
+                if !changed_files.contains(&canonical_path) {
+                    let skipped_file = SkippedFile {
+                        file_path:absolute_file_path,
+                        skip_reason:SkipReason::Unchanged { git_ref:git_ref.clone() },
+                        skip_phase:SkipPhase::Queuing
+                    };
+
+                    skipped_files.push(skipped_file);
+                    continue;
+                }
+            }
+
+            let is_gzipped:bool = is_gzip_file(&path_obj, search_options.decompress_gzip);
+
+            // An archive's entries are filtered by their own extensions and sizes instead of the archive's, see queue_archive_entries.
+            let is_archive:bool = is_zip_archive(&path_obj, search_options.search_zip);
+
+            // Falls back to the compressed size if the gzip trailer can't be read, the decoder will report the file as corrupt later on.
+            let file_size:u64 = if is_gzipped {
+                gzip_decompressed_size(&path_obj).unwrap_or(file_metadata.len())
+            } else {
+                file_metadata.len()
+            };
+
+            // The extension filters also look past the .gz of a gzipped file, so that -ext .log still queues app.log.gz.
+            let inner_path:PathBuf = if is_gzipped { path_obj.with_extension("") } else { path_obj.clone() };
+
+            if has_any_extension(&path_obj, &search_options.excluded_extensions) || has_any_extension(&inner_path, &search_options.excluded_extensions) {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::ExtensionExcluded,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            if !is_archive && has_binary_extension(&path_obj, search_options) {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::BinaryExtension,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            if extensions_matter && !is_archive && !has_any_extension(&path_obj, &search_options.file_extensions) && !has_any_extension(&inner_path, &search_options.file_extensions) {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::ExtensionMismatch,
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            // Skip the file if the file size matters, and the file size is greater than the provided maximum.
+            if file_size_matters && !is_archive && file_size > search_options.max_file_size {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::TooLarge { size:file_size, limit:search_options.max_file_size },
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            // Skip the file if the minimum file size matters, and the file size is less than the provided minimum.
+            if minimum_size_matters && !is_archive && file_size < search_options.min_file_size {
+                let skipped_file = SkippedFile {
+                    file_path:absolute_file_path,
+                    skip_reason:SkipReason::TooSmall { size:file_size, limit:search_options.min_file_size },
+                    skip_phase:SkipPhase::Queuing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+
+            // The modification time comes from the metadata that was already fetched for the size check.
+            if let Some(max_age_seconds) = search_options.max_age_seconds {
+                let modified_time:SystemTime = match file_metadata.modified() {
+                    Ok(modified_time) => modified_time,
+                    Err(error) => {
+                        let skipped_file = SkippedFile {
+                            file_path:absolute_file_path,
+                            skip_reason:SkipReason::IoError(format!("Couldn't retrieve the modification time, which -mtime requires, error: {:?}", error)),
+                            skip_phase:SkipPhase::Queuing
+                        };
+
+                        skipped_files.push(skipped_file);
+                        continue;
+                    }
+                };
+
+                // Files modified in the future count as brand new.
+                let file_age:Duration = queue_start.duration_since(modified_time).unwrap_or_default();
+
+                if file_age.as_secs() > max_age_seconds {
+                    let skipped_file = SkippedFile {
+                        file_path:absolute_file_path,
+                        skip_reason:SkipReason::TooOld { age_seconds:file_age.as_secs(), limit_seconds:max_age_seconds },
+                        skip_phase:SkipPhase::Queuing
+                    };
+
+                    skipped_files.push(skipped_file);
+                    continue;
+                }
+            }
+
+            // UTF-16 text is full of NUL bytes, so binary detection only applies to raw byte matching.
+            // Archive entries are only sampled once they're decompressed for searching.
+            if search_options.text_only && !is_archive && !matches!(search_options.encoding, FileEncoding::Utf16Le | FileEncoding::Utf16Be) {
+                let binary_sample:Vec<u8> = match read_binary_sample(&path_obj, is_gzipped, search_options.retry_count) {
+                    Ok(binary_sample) => binary_sample,
+                    Err(error) => {
+                        let skipped_file = SkippedFile {
+                            file_path:absolute_file_path,
+                            skip_reason:SkipReason::IoError(format!("Error when sampling the file for binary detection: {:?}", error)),
+                            skip_phase:SkipPhase::Queuing
+                        };
+
+                        skipped_files.push(skipped_file);
+                        continue;
+                    }
+                };
+
+                // With -enc auto, files detected as UTF-16 are text despite their NUL bytes.
+                let detected_utf16:bool = search_options.encoding == FileEncoding::Auto
+                    && matches!(sniff_encoding(&binary_sample), SniffedEncoding::Decoded(_));
+
+                if !detected_utf16 && !search_options.null_data && is_binary_sample(&binary_sample) {
+                    let skipped_file = SkippedFile {
+                        file_path:absolute_file_path,
+                        skip_reason:SkipReason::BinaryDetected,
+                        skip_phase:SkipPhase::Queuing
+                    };
+
+                    skipped_files.push(skipped_file);
+                    continue;
+                }
+            }
+
+            if is_archive {
+                queue_archive_entries(&absolute_file_path, file_metadata.modified().ok(), search_options, &mut queued_files, &mut skipped_files);
+
+                if file_count_matters && queued_files.len() >= search_options.max_files {
+                    queued_files.truncate(search_options.max_files);
+                    break;
+                }
+
+                continue;
+            }
+
+            queued_files.push(QueuedFile {
+                file_path:absolute_file_path,
+                file_size,
+                modified_time:file_metadata.modified().ok(),
+                archive_entry:None
+            });
+
+            // If the amount of queued files has reached the maximum, break and proceed with the search.
+            if file_count_matters && queued_files.len() >= search_options.max_files {
+                break;
+            }
+        }
+        
+        if progress_due(&mut last_progress, search_options.progress_interval_ms) {
+            queue_progress.set_message(format!("Queueing files.. {} / {} Files have been queued..", queued_files.len(), index + 1));
+            queue_progress.tick();
+        }
+    }
+
+    queue_progress.finish_and_clear();
+
+    if search_options.skip_largest_percent > 0.0 {
+        skip_largest_files(&mut queued_files, &mut skipped_files, search_options.skip_largest_percent);
+    }
+
+    for skipped_file in &skipped_files {
+        debug!("Skipped {}: {}", skipped_file.file_path, skipped_file.skip_reason);
+    }
+
+    info!("Queued {} files and skipped {} in {:.3} seconds", queued_files.len(), skipped_files.len(), queue_start.elapsed().unwrap_or_default().as_secs_f64());
+
+    Ok(FileQueue {
+        queued_files,
+        skipped_files
+    })
+}
+
+// Bumped whenever the layout of the cache file changes, so that older caches are discarded rather than misread.
+const CACHE_VERSION:u32 = 1;
+
+// Every option that affects the outcome of searching a file, a cache written with any of them different is discarded.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct CacheKey {
+    cache_version:u32,
+    directories:Vec<String>,
+    patterns:Vec<String>,
+    literal_patterns:Vec<String>,
+    hex_patterns:Vec<String>,
+    anchor_patterns:bool,
+    regex_mode:bool,
+    case_insensitive:bool,
+    require_all_patterns:bool,
+    whole_words:bool,
+    max_matches:usize,
+    min_matches:usize,
+    max_line_length:usize,
+    overlapping:bool,
+    stop_at_first:bool,
+    presence_only:bool,
+    capture_sample:bool,
+    score_matches:bool,
+    pattern_weights:BTreeMap<String, u64>,
+    null_data:bool,
+    decompress_gzip:bool,
+    search_zip:bool,
+    canonical_paths:bool,
+    encoding:FileEncoding,
+    match_target:MatchTarget
+}
+
+impl CacheKey {
+    fn new(search_options:&SearchOptions) -> CacheKey {
+        CacheKey {
+            cache_version:CACHE_VERSION,
+            directories:search_options.directories.clone(),
+            patterns:search_options.patterns.clone(),
+            literal_patterns:search_options.literal_patterns.clone(),
+            hex_patterns:search_options.hex_patterns.clone(),
+            anchor_patterns:search_options.anchor_patterns,
+            regex_mode:search_options.regex_mode,
+            case_insensitive:search_options.case_insensitive,
+            require_all_patterns:search_options.require_all_patterns,
+            whole_words:search_options.whole_words,
+            max_matches:search_options.max_matches,
+            min_matches:search_options.min_matches,
+            max_line_length:search_options.max_line_length,
+            overlapping:search_options.overlapping,
+            stop_at_first:search_options.stop_at_first,
+            presence_only:search_options.presence_only,
+            capture_sample:search_options.capture_sample,
+            score_matches:search_options.score_matches,
+            pattern_weights:search_options.pattern_weights.clone().into_iter().collect(),
+            null_data:search_options.null_data,
+            decompress_gzip:search_options.decompress_gzip,
+            search_zip:search_options.search_zip,
+            canonical_paths:search_options.canonical_paths,
+            encoding:search_options.encoding,
+            match_target:search_options.match_target
+        }
+    }
+}
+
+// A searched file as it was when cached, None meaning that it didn't match.
+#[derive(Serialize, Deserialize)]
+struct CachedFile {
+    modified_time:Duration,
+    file_size:u64,
+    matched_file:Option<MatchedFile>
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchCache {
+    cache_key:CacheKey,
+    cached_files:HashMap<String, CachedFile>
+}
+
+// Reads the cache at the provided path, starting over with an empty one if there's none yet, it can't be read, or it was written with different options.
+fn load_search_cache(cache_path:&str, search_options:&SearchOptions) -> SearchCache {
+    let cache_key:CacheKey = CacheKey::new(search_options);
+
+    let cache_contents:Vec<u8> = match fs::read(cache_path) {
+        Ok(cache_contents) => cache_contents,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => {
+            warn!("Couldn't read the cache @ {}, starting over, error: {}", cache_path, error);
+            Vec::new()
+        }
+    };
+
+    let search_cache:Option<SearchCache> = if cache_contents.is_empty() {
+        None
+    } else {
+        match serde_json::from_slice::<SearchCache>(&cache_contents) {
+            Ok(search_cache) if search_cache.cache_key == cache_key => Some(search_cache),
+
+            Ok(_) => {
+                info!("The cache @ {} was written with different options, starting over", cache_path);
+                None
+            }
+
+            Err(error) => {
+                warn!("The cache @ {} is malformed, starting over, error: {}", cache_path, error);
+                None
+            }
+        }
+    };
+
+    search_cache.unwrap_or(SearchCache { cache_key, cached_files:HashMap::new() })
+}
+
+// The cached outcome of the file, if it hasn't been modified since. Files without a modification time are always searched again.
+fn cached_outcome(search_cache:&SearchCache, queued_file:&QueuedFile) -> Option<SearchOutcome> {
+    let modified_time:Duration = queued_file.modified_time?.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    let cached_file:&CachedFile = search_cache.cached_files.get(&queued_file.file_path)?;
+
+    if cached_file.modified_time != modified_time || cached_file.file_size != queued_file.file_size {
+        return None;
+    }
+
+    Some(match &cached_file.matched_file {
+        Some(matched_file) => SearchOutcome::Matched(matched_file.clone()),
+        None => SearchOutcome::Unmatched(queued_file.file_path.clone())
+    })
+}
+
+fn cache_outcome(search_cache:&mut SearchCache, queued_file:&QueuedFile, search_outcome:&SearchOutcome) {
+    let modified_time:Duration = match queued_file.modified_time.and_then(|modified_time| modified_time.duration_since(SystemTime::UNIX_EPOCH).ok()) {
+        Some(modified_time) => modified_time,
+        None => return
+    };
+
+    // A skipped file is searched again next time, the reason it was skipped may well be gone by then.
+    let matched_file:Option<MatchedFile> = match search_outcome {
+        SearchOutcome::Matched(matched_file) => Some(matched_file.clone()),
+        SearchOutcome::Unmatched(_) => None,
+        SearchOutcome::Skipped(_) => {
+            search_cache.cached_files.remove(&queued_file.file_path);
+            return;
+        }
+    };
+
+    search_cache.cached_files.insert(queued_file.file_path.clone(), CachedFile { modified_time, file_size:queued_file.file_size, matched_file });
+}
+
+// Writes the cache next to its destination first and then moves it into place, so that an interrupted run never leaves half a cache behind.
+fn save_search_cache(cache_path:&str, search_cache:&SearchCache) -> Result<(), String> {
+    let temporary_path:String = format!("{}.tmp", cache_path);
+
+    let cache_contents:Vec<u8> = match serde_json::to_vec(search_cache) {
+        Ok(cache_contents) => cache_contents,
+        Err(error) => return Err(format!("Couldn't serialize the cache, error: {}", error))
+    };
+
+    if let Err(error) = fs::write(&temporary_path, cache_contents).and_then(|_| fs::rename(&temporary_path, cache_path)) {
+        return Err(format!("Couldn't write the cache @ {}, error: {}", cache_path, error));
+    }
+
+    Ok(())
+}
+
+/// Queues every file below the provided directories that passes the provided filters, and searches through them for the provided patterns.
+pub fn perform_search(search_options:&SearchOptions) -> Result<SearchResults, String> {
+    perform_search_streaming(search_options, |_| ())
+}
+
+/// Same as perform_search, but also hands the outcome of every file to on_searched as soon as it's been searched, so that results can be
+/// reported before the whole search is done. It's called from the searching threads, in no particular order, and doesn't see files that
+/// were skipped while queuing.
+pub fn perform_search_streaming<F>(search_options:&SearchOptions, on_searched:F) -> Result<SearchResults, String> where F:Fn(&SearchOutcome) + Sync {
+    let search_start:Instant = Instant::now();
+
+    let pattern_matcher:PatternMatcher = build_search_matcher(search_options)?;
+    debug!("Built the matcher for {} patterns in {:.3} seconds", pattern_count(search_options), search_start.elapsed().as_secs_f64());
+
+    let file_queue:FileQueue = queue_files(search_options)?;
+
+    // Files that are in the cache, unchanged, aren't searched again, their outcomes are handed over right away.
+    let mut search_cache:Option<SearchCache> = search_options.cache_path.as_ref().map(|cache_path| load_search_cache(cache_path, search_options));
+    let mut cached_outcomes:Vec<SearchOutcome> = Vec::new();
+    let search_fallbacks:Mutex<Vec<SkippedFile>> = Mutex::new(Vec::new());
+
+    let queued_files:Vec<QueuedFile> = match &search_cache {
+        Some(search_cache) => file_queue.queued_files.into_iter().filter(|queued_file| match cached_outcome(search_cache, queued_file) {
+            Some(search_outcome) => {
+                let search_outcome:SearchOutcome = canonicalize_outcome(search_outcome, queued_file, search_options, &search_fallbacks);
+                on_searched(&search_outcome);
+                cached_outcomes.push(search_outcome);
+                false
+            }
+
+            None => true
+        }).collect(),
+
+        None => file_queue.queued_files
+    };
+
+    if search_cache.is_some() {
+        info!("Reused the cached outcomes of {} files, {} are searched again", cached_outcomes.len(), queued_files.len());
+    }
+
+    let mut search_results = SearchResults {
+        matched_files:Vec::new(),
+        skipped_files:file_queue.skipped_files,
+        unmatched_files:Vec::new(),
+        bytes_read:0,
+        elapsed_seconds:0.0,
+        pattern_tally:search_patterns(search_options).map(|pattern| (pattern.clone(), 0)).collect(),
+        search_fallbacks:Vec::new()
+    };
+
+    let thread_pool = match ThreadPoolBuilder::new().num_threads(search_options.max_threads).build() {
+        Ok(thread_pool) => thread_pool,
+        Err(error) => return Err(format!("Couldn't build the thread pool used for searching, error: {:?}", error))
+    };
+
+    // The length is set up front, so the percentage and ETA are accurate from the first file onwards.
+    let search_progress:ProgressBar = build_progress_bar(Some(queued_files.len() as u64), search_options.show_progress);
+    let searched_count:AtomicU64 = AtomicU64::new(0);
+    let total_bytes_read:AtomicU64 = AtomicU64::new(0);
+
+    // The cached files that matched count towards max_matched_files too.
+    let matched_count:AtomicUsize = AtomicUsize::new(cached_outcomes.iter().filter(|search_outcome| matches!(search_outcome, SearchOutcome::Matched(_))).count());
+
+    let searching_start:Instant = Instant::now();
+
+    // Without contents to read, the pipeline's readers would have nothing to do.
+    let use_pipeline:bool = (search_options.reader_threads > 0 || search_options.worker_threads > 0) && search_options.match_target != MatchTarget::Paths;
+
+    let search_outcomes:Vec<SearchOutcome> = thread::scope(|thread_scope| {
+        // The renderer stops once the sender is dropped, right after the last file was searched.
+        let (searching_sender, searching_receiver) = mpsc::channel::<()>();
+
+        if search_options.show_progress {
+            thread_scope.spawn(|| render_search_progress(&search_progress, &searched_count, &total_bytes_read, searching_receiver, search_options.progress_interval_ms));
+        }
+
+        let progress_sender:Option<&mpsc::Sender<()>> = if search_options.show_progress && search_options.progress_interval_ms == 0 { Some(&searching_sender) } else { None };
+
+        let search_outcomes:Vec<SearchOutcome> = if use_pipeline {
+            let search_state = SearchState {
+                search_options,
+                pattern_matcher:&pattern_matcher,
+                total_bytes_read:&total_bytes_read,
+                search_fallbacks:&search_fallbacks,
+                searched_count:&searched_count,
+                matched_count:&matched_count,
+                progress_sender
+            };
+
+            search_pipelined(&queued_files, &search_state, &on_searched)
+        } else {
+            thread_pool.install(|| queued_files.par_iter().map(|queued_file| {
+                let search_outcome:SearchOutcome = if budget_exhausted(search_options, &total_bytes_read) {
+                    SearchOutcome::Skipped(budget_skip(queued_file))
+                } else if enough_files_matched(search_options, &matched_count) {
+                    SearchOutcome::Skipped(enough_matched_skip(queued_file))
+                } else {
+                    let search_outcome:SearchOutcome = search_file(queued_file, search_options, &pattern_matcher, &total_bytes_read, &search_fallbacks);
+                    canonicalize_outcome(search_outcome, queued_file, search_options, &search_fallbacks)
+                };
+
+                if let SearchOutcome::Matched(_) = search_outcome {
+                    matched_count.fetch_add(1, Ordering::SeqCst);
+                }
+
+                on_searched(&search_outcome);
+                note_searched(&searched_count, progress_sender);
+                search_outcome
+            }).collect())
+        };
+
+        drop(searching_sender);
+        search_outcomes
+    });
+
+    search_progress.finish_and_clear();
+    info!("Searched {} files in {:.3} seconds", queued_files.len(), searching_start.elapsed().as_secs_f64());
+
+    let budget_skips:usize = search_outcomes.iter().filter(|search_outcome| matches!(search_outcome, SearchOutcome::Skipped(skipped_file) if skipped_file.skip_reason == SkipReason::BudgetExhausted)).count();
+
+    if budget_skips > 0 {
+        warn!("The byte budget of {} bytes was exhausted after searching {} files, the other {} were skipped", search_options.max_total_bytes, queued_files.len() - budget_skips, budget_skips);
+    }
+
+    let enough_matched_skips:usize = search_outcomes.iter().filter(|search_outcome| matches!(search_outcome, SearchOutcome::Skipped(skipped_file) if skipped_file.skip_reason == SkipReason::EnoughMatched)).count();
+
+    if enough_matched_skips > 0 {
+        info!("Stopped once {} files matched, the other {} were skipped", search_options.max_matched_files, enough_matched_skips);
+    }
+
+    // The outcomes are in the same order as the queued files, whichever way they were searched.
+    if let (Some(search_cache), Some(cache_path)) = (&mut search_cache, &search_options.cache_path) {
+        for (queued_file, search_outcome) in queued_files.iter().zip(&search_outcomes) {
+            cache_outcome(search_cache, queued_file, search_outcome);
+        }
+
+        // The search itself went fine, so a cache that can't be written only costs the next run its speedup.
+        if let Err(error) = save_search_cache(cache_path, search_cache) {
+            warn!("{}", error);
+        }
+    }
+
+    for search_outcome in cached_outcomes.into_iter().chain(search_outcomes) {
+        collect_outcome(&mut search_results, search_outcome);
+    }
+
+    // Sort every bucket by path, so that the results don't depend on the order the threads finished in. The skipped files are grouped by phase first.
+    search_results.matched_files.sort_by(|previous, current| previous.file_path.cmp(&current.file_path));
+    search_results.skipped_files.sort_by(|previous, current| previous.skip_phase.cmp(&current.skip_phase).then_with(|| previous.file_path.cmp(&current.file_path)));
+    search_results.unmatched_files.sort();
+
+    search_results.search_fallbacks = search_fallbacks.into_inner().unwrap_or_default();
+    search_results.search_fallbacks.sort_by(|previous, current| previous.file_path.cmp(&current.file_path));
+
+    search_results.bytes_read = total_bytes_read.load(Ordering::SeqCst);
+    search_results.elapsed_seconds = search_start.elapsed().as_secs_f64();
+
+    Ok(search_results)
+}
+
+// Sorts the outcome of a file into the results' buckets, and tallies its matches.
+fn collect_outcome(search_results:&mut SearchResults, search_outcome:SearchOutcome) {
+    match search_outcome {
+        SearchOutcome::Matched(matched_file) => {
+            for match_location in &matched_file.match_locations {
+                *search_results.pattern_tally.entry(match_location.pattern.clone()).or_insert(0) += 1;
+            }
+
+            search_results.matched_files.push(matched_file);
+        }
+
+        SearchOutcome::Unmatched(unmatched_file) => search_results.unmatched_files.push(unmatched_file),
+        SearchOutcome::Skipped(skipped_file) => {
+            debug!("Skipped {}: {}", skipped_file.file_path, skipped_file.skip_reason);
+            search_results.skipped_files.push(skipped_file);
+        }
+    }
+}
+
+/// Searches whatever is read from reader as if it were the contents of a single file named stream_name, e.g. <stdin> for piped input, which
+/// ends up matched, unmatched, or skipped if reading it fails, and is handed to on_searched like with perform_search_streaming. Literal patterns
+/// are matched as the contents come in, so a large input is never held in memory whole, whereas regexes and UTF-16 need all of it first.
+///
+/// None of the queue filters apply, except that text_only skips the stream if it turns out to be binary, and neither do memory_map, decompress_gzip
+/// or cache_path. The patterns are only matched against the contents, whatever the match_target, and FileEncoding::Auto matches the raw bytes.
+pub fn search_stream<R:Read, F>(stream_name:&str, reader:R, search_options:&SearchOptions, on_searched:F) -> Result<SearchResults, String> where F:Fn(&SearchOutcome) {
+    let search_start:Instant = Instant::now();
+    let pattern_matcher:PatternMatcher = build_search_matcher(search_options)?;
+    let stream_name:String = String::from(stream_name);
+
+    let file_hits_result:Result<FileHits, SkipReason> = match decoding_for(search_options.encoding) {
+        Some(encoding) => decode_file(&stream_name, reader, encoding).map(|(decoded_contents, stream_size)| {
+            let mut file_hits:FileHits = find_hits_in(&decoded_contents, &pattern_matcher, search_options);
+            file_hits.bytes_read = stream_size;
+            file_hits
+        }),
+
+        None => find_hits(reader, &pattern_matcher, search_options).map_err(|error| read_error_reason(&stream_name, false, error))
+    };
+
+    let total_bytes_read:AtomicU64 = AtomicU64::new(0);
+
+    let search_outcome:SearchOutcome = match file_hits_result {
+        Ok(file_hits) if search_options.text_only && file_hits.is_binary => {
+            let skipped_file = SkippedFile {
+                file_path:stream_name,
+                skip_reason:SkipReason::BinaryDetected,
+                skip_phase:SkipPhase::Searching
+            };
+
+            SearchOutcome::Skipped(skipped_file)
+        }
+
+        Ok(file_hits) => {
+            let queued_stream = QueuedFile { file_path:stream_name, file_size:file_hits.bytes_read, modified_time:None, archive_entry:None };
+            classify_file_hits(&queued_stream, file_hits, Vec::new(), search_options, &total_bytes_read)
+        }
+
+        Err(skip_reason) => {
+            let skipped_file = SkippedFile {
+                file_path:stream_name,
+                skip_reason,
+                skip_phase:SkipPhase::Searching
+            };
+
+            SearchOutcome::Skipped(skipped_file)
+        }
+    };
+
+    on_searched(&search_outcome);
+
+    let mut search_results = SearchResults {
+        matched_files:Vec::new(),
+        skipped_files:Vec::new(),
+        unmatched_files:Vec::new(),
+        bytes_read:0,
+        elapsed_seconds:0.0,
+        pattern_tally:search_patterns(search_options).map(|pattern| (pattern.clone(), 0)).collect(),
+        search_fallbacks:Vec::new()
+    };
+
+    collect_outcome(&mut search_results, search_outcome);
+
+    search_results.bytes_read = total_bytes_read.load(Ordering::SeqCst);
+    search_results.elapsed_seconds = search_start.elapsed().as_secs_f64();
+
+    Ok(search_results)
+}
+
+// A single match found within a file.
+struct PatternHit {
+    pattern_index:usize,
+    byte_offset:usize,
+    match_length:usize,
+    line_number:usize
+}
+
+// Every match found within a file, along with whether the file looked binary, and whether matching stopped at max_matches.
+struct FileHits {
+    pattern_hits:Vec<PatternHit>,
+    is_binary:bool,
+    bytes_read:u64,
+    match_cap_reached:bool,
+    first_match_sample:Option<String>,
+    line_too_long:bool,
+    timed_out:bool
+}
+
+// How many bytes of the line on either side of the first match are included in its sample, and how much of the match itself at most.
+const SAMPLE_CONTEXT_SIZE:usize = 20;
+const SAMPLE_MATCH_SIZE:usize = 80;
+
+// The snippet around the match at the provided offsets, which never reaches past the match's line. Cutting in the middle of a multi-byte character
+// leaves a replacement character at the edge, which is trimmed off along with surrounding whitespace.
+fn sample_match(contents:&[u8], match_start:usize, match_end:usize) -> String {
+    let match_end:usize = match_end.min(match_start + SAMPLE_MATCH_SIZE).min(contents.len());
+
+    let mut sample_start:usize = match_start.saturating_sub(SAMPLE_CONTEXT_SIZE);
+    let mut sample_end:usize = (match_end + SAMPLE_CONTEXT_SIZE).min(contents.len());
+
+    if let Some(newline_offset) = contents[sample_start..match_start].iter().rposition(|&sample_byte| sample_byte == b'\n') {
+        sample_start += newline_offset + 1;
+    }
+
+    if let Some(newline_offset) = contents[match_end..sample_end].iter().position(|&sample_byte| sample_byte == b'\n') {
+        sample_end = match_end + newline_offset;
+    }
+
+    let sample:String = String::from_utf8_lossy(&contents[sample_start..sample_end]).chars().map(|sample_char| if sample_char.is_control() { ' ' } else { sample_char }).collect();
+    sample.trim_matches(|sample_char:char| sample_char == char::REPLACEMENT_CHARACTER || sample_char.is_whitespace()).to_string()
+}
+
+// The amount of bytes read at a time when streaming a file through the Aho-Corasick automaton.
+const STREAM_CHUNK_SIZE:usize = 64 * 1024;
+
+fn count_lines(bytes:&[u8], line_separator:u8) -> usize {
+    bytes.iter().filter(|&&byte| byte == line_separator).count()
+}
+
+// With null_data, the records are counted in place of the lines.
+fn line_separator(search_options:&SearchOptions) -> u8 {
+    if search_options.null_data { b'\0' } else { b'\n' }
+}
+
+// The length of the longest line within the bytes, where the first one continues a line that's already current_line bytes long, along with
+// how long the line they end on is so far. Lines are always split at newlines here, even with null_data, since it's about readability.
+fn measure_lines(bytes:&[u8], current_line:usize) -> (usize, usize) {
+    let mut lines = bytes.split(|&byte| byte == b'\n');
+
+    let mut line_length:usize = current_line + lines.next().map(|line| line.len()).unwrap_or(0);
+    let mut longest_line:usize = line_length;
+
+    for line in lines {
+        line_length = line.len();
+        longest_line = longest_line.max(line_length);
+    }
+
+    (longest_line, line_length)
+}
+
+// Once the search of a file ends early, the rest of it is still read through for max_line_length, though not matched. Returns whether a line
+// turned out longer than that, along with how many more bytes were read.
+fn read_line_lengths<R:Read>(reader:&mut R, chunk:&mut [u8], mut current_line:usize, max_line_length:usize) -> io::Result<(bool, u64)> {
+    let mut bytes_left:u64 = 0;
+
+    loop {
+        let bytes_read:usize = read_chunk(reader, chunk)?;
+
+        if bytes_read == 0 {
+            return Ok((false, bytes_left));
+        }
+
+        bytes_left += bytes_read as u64;
+
+        let (longest_line, line_length) = measure_lines(&chunk[..bytes_read], current_line);
+        current_line = line_length;
+
+        if longest_line > max_line_length {
+            return Ok((true, bytes_left));
+        }
+    }
+}
+
+// Whether the contents hold a line longer than max_line_length, never if there's no limit.
+fn has_long_line(file_contents:&[u8], search_options:&SearchOptions) -> bool {
+    search_options.max_line_length > 0 && measure_lines(file_contents, 0).0 > search_options.max_line_length
+}
+
+// Fills the provided chunk from the reader, only returning less than a full chunk once the end of the reader is reached.
+fn read_chunk<R:Read>(reader:&mut R, chunk:&mut [u8]) -> io::Result<usize> {
+    let mut bytes_filled:usize = 0;
+
+    while bytes_filled < chunk.len() {
+        match reader.read(&mut chunk[bytes_filled..]) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_filled += bytes_read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error)
+        };
+    }
+
+    Ok(bytes_filled)
+}
+
+// Whether the match is surrounded by non-word characters, i.e. not [A-Za-z0-9_], or the bounds of the contents. The contents start at
+// window_offset within the file, and their start is only a bound when that's the start of the file.
+fn is_whole_word(file_contents:&[u8], match_start:usize, match_end:usize, window_offset:usize) -> bool {
+    let is_word_byte = |byte:&u8| byte.is_ascii_alphanumeric() || *byte == b'_';
+
+    let boundary_before:bool = match match_start.checked_sub(1) {
+        Some(byte_before) => !is_word_byte(&file_contents[byte_before]),
+        None => window_offset == 0
+    };
+    let boundary_after:bool = match_end == file_contents.len() || !is_word_byte(&file_contents[match_end]);
+
+    boundary_before && boundary_after
+}
+
+// Repeated matches are still needed for min_matches, and for the records after the first with null_data.
+fn is_presence_only(search_options:&SearchOptions) -> bool {
+    search_options.presence_only && search_options.min_matches <= 1 && !search_options.null_data
+}
+
+// Overlapping matches only change the counts, so they aren't searched for when only presence matters.
+fn counts_overlaps(search_options:&SearchOptions) -> bool {
+    search_options.overlapping && !is_presence_only(search_options)
+}
+
+// With stop_at_first, a file is done once it's known to match, which takes one match, or with require_all_patterns one match of every pattern.
+// With presence_only, it's done once every pattern was found, since nothing more could change the outcome.
+fn is_known_match(found_pattern_count:usize, search_options:&SearchOptions) -> bool {
+    let found_every_pattern:bool = found_pattern_count == pattern_count(search_options);
+
+    (is_presence_only(search_options) && found_every_pattern) || search_options.stop_at_first && if search_options.require_all_patterns {
+        found_every_pattern
+    } else {
+        found_pattern_count > 0
+    }
+}
+
+// The state carried over between the windows of a streamed file. A file that's already in memory is simply a single window.
+struct LiteralScan {
+    pattern_hits:Vec<PatternHit>,
+    found_patterns:HashSet<usize>,
+
+    // The offset within the file of the first byte in the window, and the amount of lines before it.
+    window_offset:usize,
+    lines_before_window:usize,
+    line_separator:u8,
+
+    // Matches don't overlap, so searching resumes from the end of the last match, even if that's within the carried over bytes.
+    last_match_end:usize,
+
+    // With overlapping matches, every window is searched in full instead, and the matches ending before this offset were already found.
+    scanned_up_to:usize,
+
+    // Whether the patterns anchored to the start were already compared against the first window.
+    checked_start_anchors:bool,
+
+    first_match_sample:Option<String>,
+
+    // Whether the scan was given up on because match_timeout_ms passed, in which case its hits are incomplete.
+    timed_out:bool
+}
+
+impl LiteralScan {
+    fn new(line_separator:u8) -> LiteralScan {
+        LiteralScan {
+            pattern_hits:Vec::new(),
+            found_patterns:HashSet::new(),
+            window_offset:0,
+            lines_before_window:0,
+            line_separator,
+            last_match_end:0,
+            scanned_up_to:0,
+            checked_start_anchors:false,
+            first_match_sample:None,
+            timed_out:false
+        }
+    }
+
+    // Records a match, returning Some once the rest of the file doesn't need to be searched, holding whether max_matches was the reason.
+    fn push_hit(&mut self, pattern_hit:PatternHit, window:&[u8], search_options:&SearchOptions) -> Option<bool> {
+        // With presence_only, repeated matches of a pattern that was already found are dropped.
+        if !self.found_patterns.insert(pattern_hit.pattern_index) && is_presence_only(search_options) {
+            return None;
+        }
+
+        if search_options.capture_sample && self.first_match_sample.is_none() {
+            let match_start:usize = pattern_hit.byte_offset - self.window_offset;
+            self.first_match_sample = Some(sample_match(window, match_start, match_start + pattern_hit.match_length));
+        }
+
+        self.pattern_hits.push(pattern_hit);
+
+        // The rest of the file is never read, which is where -first saves time on large files.
+        if is_known_match(self.found_patterns.len(), search_options) {
+            return Some(false);
+        }
+
+        if search_options.max_matches > 0 && self.pattern_hits.len() >= search_options.max_matches {
+            return Some(true);
+        }
+
+        None
+    }
+
+    // Matches the window, returning Some once the rest of the file doesn't need to be searched, holding whether max_matches was the reason.
+    // The deadline is checked before the window and between its matches, and once it passed timed_out is set and Some(false) returned.
+    fn scan_window(&mut self, window:&[u8], reached_end:bool, literal_matcher:&LiteralMatcher, search_options:&SearchOptions, match_deadline:Option<Instant>) -> Option<bool> {
+        if self.check_deadline(match_deadline) {
+            return Some(false);
+        }
+
+        if !self.checked_start_anchors {
+            self.checked_start_anchors = true;
+
+            for anchored_pattern in literal_matcher.anchored_patterns.iter().filter(|anchored_pattern| anchored_pattern.at_start && !anchored_pattern.at_end) {
+                let match_end:usize = anchored_pattern.pattern_bytes.len();
+
+                if !anchored_pattern.matches_at(window, 0, literal_matcher.case_insensitive) || (search_options.whole_words && !is_whole_word(window, 0, match_end, self.window_offset)) {
+                    continue;
+                }
+
+                let pattern_hit = PatternHit { pattern_index:anchored_pattern.pattern_index, byte_offset:0, match_length:match_end, line_number:1 };
+
+                if let Some(match_cap_reached) = self.push_hit(pattern_hit, window, search_options) {
+                    return Some(match_cap_reached);
+                }
+            }
+        }
+
+        if counts_overlaps(search_options) {
+            if let Some(match_cap_reached) = self.scan_overlapping(window, reached_end, literal_matcher, search_options, match_deadline) {
+                return Some(match_cap_reached);
+            }
+        } else if let Some(match_cap_reached) = self.scan_leftmost(window, reached_end, literal_matcher, search_options, match_deadline) {
+            return Some(match_cap_reached);
+        }
+
+        if reached_end {
+            return self.scan_end_anchors(window, literal_matcher, search_options);
+        }
+
+        None
+    }
+
+    // Sets timed_out once the deadline passed, returning whether it did.
+    fn check_deadline(&mut self, match_deadline:Option<Instant>) -> bool {
+        self.timed_out = self.timed_out || deadline_passed(match_deadline);
+        self.timed_out
+    }
+
+    fn scan_leftmost(&mut self, window:&[u8], reached_end:bool, literal_matcher:&LiteralMatcher, search_options:&SearchOptions, match_deadline:Option<Instant>) -> Option<bool> {
+        let search_start:usize = self.last_match_end.saturating_sub(self.window_offset);
+
+        let mut line_number:usize = self.lines_before_window + 1 + count_lines(&window[..search_start], self.line_separator);
+        let mut counted_up_to:usize = search_start;
+
+        for matched_pattern in literal_matcher.aho_corasick_search_alg.find_iter(&window[search_start..]) {
+            if self.check_deadline(match_deadline) {
+                return Some(false);
+            }
+
+            let match_start:usize = search_start + matched_pattern.start();
+            let match_end:usize = search_start + matched_pattern.end();
+
+            if search_options.whole_words {
+                // The byte after a match at the end of the window isn't known yet, it'll be found again in the next window.
+                if match_end == window.len() && !reached_end {
+                    break;
+                }
+
+                if !is_whole_word(window, match_start, match_end, self.window_offset) {
+                    self.last_match_end = self.window_offset + match_end;
+                    continue;
+                }
+            }
+
+            line_number += count_lines(&window[counted_up_to..match_start], self.line_separator);
+            counted_up_to = match_start;
+
+            let pattern_hit = PatternHit {
+                pattern_index:literal_matcher.pattern_indices[matched_pattern.pattern()],
+                byte_offset:self.window_offset + match_start,
+                match_length:match_end - match_start,
+                line_number
+            };
+
+            if let Some(match_cap_reached) = self.push_hit(pattern_hit, window, search_options) {
+                return Some(match_cap_reached);
+            }
+
+            self.last_match_end = self.window_offset + match_end;
+        }
+
+        None
+    }
+
+    // The overlapping matches come in the order they end in, so their starts can go back and forth, and they're sorted by offset afterwards.
+    fn scan_overlapping(&mut self, window:&[u8], reached_end:bool, literal_matcher:&LiteralMatcher, search_options:&SearchOptions, match_deadline:Option<Instant>) -> Option<bool> {
+        let mut scan_outcome:Option<bool> = None;
+        let mut line_number:usize = self.lines_before_window + 1;
+        let mut counted_up_to:usize = 0;
+
+        for matched_pattern in literal_matcher.aho_corasick_search_alg.find_overlapping_iter(window) {
+            if self.check_deadline(match_deadline) {
+                scan_outcome = Some(false);
+                break;
+            }
+
+            let match_start:usize = matched_pattern.start();
+            let match_end:usize = matched_pattern.end();
+
+            if self.window_offset + match_end <= self.scanned_up_to {
+                continue;
+            }
+
+            // The byte after a match at the end of the window isn't known yet, it'll be found again in the next window.
+            if search_options.whole_words && ((match_end == window.len() && !reached_end) || !is_whole_word(window, match_start, match_end, self.window_offset)) {
+                continue;
+            }
+
+            if match_start >= counted_up_to {
+                line_number += count_lines(&window[counted_up_to..match_start], self.line_separator);
+            } else {
+                line_number -= count_lines(&window[match_start..counted_up_to], self.line_separator);
+            }
+
+            counted_up_to = match_start;
+
+            let pattern_hit = PatternHit {
+                pattern_index:literal_matcher.pattern_indices[matched_pattern.pattern()],
+                byte_offset:self.window_offset + match_start,
+                match_length:match_end - match_start,
+                line_number
+            };
+
+            scan_outcome = self.push_hit(pattern_hit, window, search_options);
+
+            if scan_outcome.is_some() {
+                break;
+            }
+        }
+
+        // With -word, the matches at the very end were held back, so the next window has to look at them again.
+        let held_back:usize = if search_options.whole_words && !reached_end { 1 } else { 0 };
+        self.scanned_up_to = (self.window_offset + window.len()).saturating_sub(held_back).max(self.scanned_up_to);
+
+        // The hits before this window are already sorted, and none of this window's hits start before it.
+        let unsorted_from:usize = self.pattern_hits.partition_point(|pattern_hit| pattern_hit.byte_offset < self.window_offset);
+        self.pattern_hits[unsorted_from..].sort_by_key(|pattern_hit| pattern_hit.byte_offset);
+
+        scan_outcome
+    }
+
+    // Compares the patterns anchored to the end against the last window, which holds the end of the file. Their matches can start before
+    // the automaton's last match, so the hits are sorted by offset again afterwards.
+    fn scan_end_anchors(&mut self, window:&[u8], literal_matcher:&LiteralMatcher, search_options:&SearchOptions) -> Option<bool> {
+        let mut scan_outcome:Option<bool> = None;
+        let mut found_any:bool = false;
+
+        for anchored_pattern in literal_matcher.anchored_patterns.iter().filter(|anchored_pattern| anchored_pattern.at_end) {
+            let match_start:usize = match window.len().checked_sub(anchored_pattern.pattern_bytes.len()) {
+                Some(match_start) => match_start,
+                None => continue
+            };
+
+            // Anchored to both ends, the pattern has to be the whole file.
+            if anchored_pattern.at_start && self.window_offset + match_start != 0 {
+                continue;
+            }
+
+            if !anchored_pattern.matches_at(window, match_start, literal_matcher.case_insensitive) || (search_options.whole_words && !is_whole_word(window, match_start, window.len(), self.window_offset)) {
+                continue;
+            }
+
+            found_any = true;
+
+            let pattern_hit = PatternHit {
+                pattern_index:anchored_pattern.pattern_index,
+                byte_offset:self.window_offset + match_start,
+                match_length:anchored_pattern.pattern_bytes.len(),
+                line_number:self.lines_before_window + 1 + count_lines(&window[..match_start], self.line_separator)
+            };
+
+            scan_outcome = self.push_hit(pattern_hit, window, search_options);
+
+            if scan_outcome.is_some() {
+                break;
+            }
+        }
+
+        if found_any {
+            self.pattern_hits.sort_by_key(|pattern_hit| pattern_hit.byte_offset);
+        }
+
+        scan_outcome
+    }
+
+    // Drops all but the last overlap_size bytes of the window, which are searched again along with the next chunk.
+    fn advance_window(&mut self, window:&mut Vec<u8>, overlap_size:usize) {
+        let carry_start:usize = window.len().saturating_sub(overlap_size);
+
+        self.lines_before_window += count_lines(&window[..carry_start], self.line_separator);
+        self.window_offset += carry_start;
+        window.drain(..carry_start);
+    }
+}
+
+// Streams the reader through the automaton a chunk at a time, rather than holding the whole file in memory. The last
+// (longest pattern length) bytes of every window are carried over into the next, so matches spanning a chunk boundary are
+// still found, along with one more byte for -word, the one before a match that was held back at the end of the window.
+fn find_literal_hits<R:Read>(mut reader:R, literal_matcher:&LiteralMatcher, search_options:&SearchOptions) -> io::Result<FileHits> {
+    // The end anchors are compared against the last window, so it has to hold at least the longest of them.
+    let longest_anchor:usize = literal_matcher.anchored_patterns.iter().map(|anchored_pattern| anchored_pattern.pattern_bytes.len()).max().unwrap_or(0);
+    let longest_pattern:usize = literal_matcher.aho_corasick_search_alg.max_pattern_len().max(longest_anchor);
+    let overlap_size:usize = if search_options.whole_words { longest_pattern + 1 } else { longest_pattern };
+
+    let mut chunk:Vec<u8> = vec![0; STREAM_CHUNK_SIZE];
+    let mut window:Vec<u8> = Vec::with_capacity(overlap_size + STREAM_CHUNK_SIZE);
+
+    let mut literal_scan:LiteralScan = LiteralScan::new(line_separator(search_options));
+    let mut is_binary:bool = false;
+
+    // How long the line that the last chunk ended on is so far, for max_line_length.
+    let mut current_line:usize = 0;
+
+    let match_deadline:Option<Instant> = match_deadline(search_options);
+
+    let mut reached_end:bool = false;
+    let mut total_bytes_read:u64 = 0;
+
+    loop {
+        let bytes_read:usize = read_chunk(&mut reader, &mut chunk)?;
+
+        // A file whose size is a multiple of the chunk size only turns out to have ended now, so its last window is scanned once more
+        // knowing that, for -word and the end anchors.
+        if bytes_read == 0 {
+            if !reached_end {
+                if let Some(match_cap_reached) = literal_scan.scan_window(&window, true, literal_matcher, search_options, match_deadline) {
+                    if literal_scan.timed_out {
+                        return Ok(timed_out_hits(is_binary, total_bytes_read));
+                    }
+
+                    return Ok(FileHits { pattern_hits:literal_scan.pattern_hits, is_binary, bytes_read:total_bytes_read, match_cap_reached, first_match_sample:literal_scan.first_match_sample, line_too_long:false, timed_out:false });
+                }
+            }
+
+            break;
+        }
+
+        if bytes_read < STREAM_CHUNK_SIZE {
+            reached_end = true;
+        }
+
+        total_bytes_read += bytes_read as u64;
+
+        if deadline_passed(match_deadline) {
+            return Ok(timed_out_hits(is_binary, total_bytes_read));
+        }
+
+        if literal_scan.window_offset == 0 && window.is_empty() {
+            is_binary = is_binary_sample(&chunk[..bytes_read.min(BINARY_SAMPLE_SIZE)]);
+        }
+
+        // The rest of a file that's going to be skipped isn't worth reading.
+        if search_options.max_line_length > 0 {
+            let (longest_line, line_length) = measure_lines(&chunk[..bytes_read], current_line);
+            current_line = line_length;
+
+            if longest_line > search_options.max_line_length {
+                return Ok(FileHits { pattern_hits:Vec::new(), is_binary, bytes_read:total_bytes_read, match_cap_reached:false, first_match_sample:None, line_too_long:true, timed_out:false });
+            }
+        }
+
+        window.extend_from_slice(&chunk[..bytes_read]);
+
+        if let Some(match_cap_reached) = literal_scan.scan_window(&window, reached_end, literal_matcher, search_options, match_deadline) {
+            if literal_scan.timed_out {
+                return Ok(timed_out_hits(is_binary, total_bytes_read));
+            }
+
+            let (line_too_long, bytes_left) = if search_options.max_line_length > 0 && !reached_end {
+                read_line_lengths(&mut reader, &mut chunk, current_line, search_options.max_line_length)?
+            } else {
+                (false, 0)
+            };
+
+            return Ok(FileHits { pattern_hits:literal_scan.pattern_hits, is_binary, bytes_read:total_bytes_read + bytes_left, match_cap_reached, first_match_sample:literal_scan.first_match_sample, line_too_long, timed_out:false });
+        }
+
+        literal_scan.advance_window(&mut window, overlap_size);
+    }
+
+    Ok(FileHits { pattern_hits:literal_scan.pattern_hits, is_binary, bytes_read:total_bytes_read, match_cap_reached:false, first_match_sample:literal_scan.first_match_sample, line_too_long:false, timed_out:false })
+}
+
+// Matches contents that are already in memory, such as a memory-mapped file, without copying them anywhere.
+fn find_literal_hits_in(file_contents:&[u8], literal_matcher:&LiteralMatcher, search_options:&SearchOptions) -> FileHits {
+    let mut literal_scan:LiteralScan = LiteralScan::new(line_separator(search_options));
+
+    let match_cap_reached:bool = literal_scan.scan_window(file_contents, true, literal_matcher, search_options, match_deadline(search_options)).unwrap_or(false);
+    let is_binary:bool = is_binary_sample(&file_contents[..file_contents.len().min(BINARY_SAMPLE_SIZE)]);
+
+    if literal_scan.timed_out {
+        return timed_out_hits(is_binary, file_contents.len() as u64);
+    }
+
+    FileHits { pattern_hits:literal_scan.pattern_hits, is_binary, bytes_read:file_contents.len() as u64, match_cap_reached, first_match_sample:literal_scan.first_match_sample, line_too_long:has_long_line(file_contents, search_options), timed_out:false }
+}
+
+// Regexes can match arbitrarily long spans, so the whole file is read into memory before matching.
+fn find_regex_hits<R:Read>(mut reader:R, regex_set:&RegexSet, regexes:&[Regex], search_options:&SearchOptions) -> io::Result<FileHits> {
+    let mut file_contents:Vec<u8> = Vec::new();
+    reader.read_to_end(&mut file_contents)?;
+
+    Ok(find_regex_hits_in(&file_contents, regex_set, regexes, search_options))
+}
+
+fn find_regex_hits_in(file_contents:&[u8], regex_set:&RegexSet, regexes:&[Regex], search_options:&SearchOptions) -> FileHits {
+    // With -first, or when only the presence of each pattern matters, only the first match of each pattern is needed.
+    let hits_per_pattern:usize = if search_options.stop_at_first || is_presence_only(search_options) { 1 } else { usize::MAX };
+
+    let match_deadline:Option<Instant> = match_deadline(search_options);
+
+    // Triples of (pattern index, byte offset, match length), grouped by pattern until they're sorted by offset.
+    let mut regex_hits:Vec<(usize, usize, usize)> = Vec::new();
+
+    // A single regex scan can't be interrupted, so the deadline is checked before each of them, and for every match they find, including
+    // the ones -word rejects.
+    if deadline_passed(match_deadline) {
+        return timed_out_hits(false, file_contents.len() as u64);
+    }
+
+    for matched_index in regex_set.matches(file_contents).into_iter() {
+        if deadline_passed(match_deadline) {
+            return timed_out_hits(false, file_contents.len() as u64);
+        }
+
+        let mut pattern_hit_count:usize = 0;
+
+        for matched_regex in regex_matches(&regexes[matched_index], file_contents, search_options) {
+            if deadline_passed(match_deadline) {
+                return timed_out_hits(false, file_contents.len() as u64);
+            }
+
+            if search_options.whole_words && !is_whole_word(file_contents, matched_regex.start(), matched_regex.end(), 0) {
+                continue;
+            }
+
+            regex_hits.push((matched_index, matched_regex.start(), matched_regex.len()));
+            pattern_hit_count += 1;
+
+            if pattern_hit_count >= hits_per_pattern {
+                break;
+            }
+        }
+    }
+
+    regex_hits.sort_by_key(|&(_, byte_offset, _)| byte_offset);
+
+    // Without -all, the earliest of those first matches is enough to know the file matched.
+    if search_options.stop_at_first && !search_options.require_all_patterns {
+        regex_hits.truncate(1);
+    }
+
+    let match_cap_reached:bool = search_options.max_matches > 0 && regex_hits.len() >= search_options.max_matches;
+
+    if match_cap_reached {
+        regex_hits.truncate(search_options.max_matches);
+    }
+
+    let mut pattern_hits:Vec<PatternHit> = Vec::new();
+
+    let mut line_number:usize = 1;
+    let mut counted_up_to:usize = 0;
+
+    for (pattern_index, byte_offset, match_length) in regex_hits {
+        line_number += count_lines(&file_contents[counted_up_to..byte_offset], line_separator(search_options));
+        counted_up_to = byte_offset;
+
+        pattern_hits.push(PatternHit { pattern_index, byte_offset, match_length, line_number });
+    }
+
+    let is_binary:bool = is_binary_sample(&file_contents[..file_contents.len().min(BINARY_SAMPLE_SIZE)]);
+
+    let first_match_sample:Option<String> = pattern_hits.first().filter(|_| search_options.capture_sample).map(|first_hit| {
+        sample_match(file_contents, first_hit.byte_offset, first_hit.byte_offset + first_hit.match_length)
+    });
+
+    FileHits { pattern_hits, is_binary, bytes_read:file_contents.len() as u64, match_cap_reached, first_match_sample, line_too_long:has_long_line(file_contents, search_options), timed_out:false }
+}
+
+// Every match of the regex, non-overlapping unless overlapping matches are counted, in which case the next one is looked for
+// right past the start of the previous one rather than its end.
+fn regex_matches<'a>(regex:&'a Regex, file_contents:&'a [u8], search_options:&SearchOptions) -> Box<dyn Iterator<Item = regex::bytes::Match<'a>> + 'a> {
+    if !counts_overlaps(search_options) {
+        return Box::new(regex.find_iter(file_contents));
+    }
+
+    Box::new(std::iter::successors(regex.find(file_contents), move |previous_match| {
+        if previous_match.start() < file_contents.len() { regex.find_at(file_contents, previous_match.start() + 1) } else { None }
+    }))
+}
+
+// When matching a file that starts now has to be given up on, for match_timeout_ms.
+fn match_deadline(search_options:&SearchOptions) -> Option<Instant> {
+    if search_options.match_timeout_ms > 0 {
+        Some(Instant::now() + Duration::from_millis(search_options.match_timeout_ms))
+    } else {
+        None
+    }
+}
+
+fn deadline_passed(match_deadline:Option<Instant>) -> bool {
+    match_deadline.is_some_and(|match_deadline| Instant::now() >= match_deadline)
+}
+
+// The hits of a file whose matching was given up on, none of which are kept since they'd be incomplete.
+fn timed_out_hits(is_binary:bool, bytes_read:u64) -> FileHits {
+    FileHits { pattern_hits:Vec::new(), is_binary, bytes_read, match_cap_reached:false, first_match_sample:None, line_too_long:false, timed_out:true }
+}
+
+// Reads a single queued file and matches its contents against the provided patterns.
+fn find_hits<R:Read>(reader:R, pattern_matcher:&PatternMatcher, search_options:&SearchOptions) -> io::Result<FileHits> {
+    match pattern_matcher {
+        PatternMatcher::Literal(literal_matcher) => find_literal_hits(reader, literal_matcher, search_options),
+        PatternMatcher::Regex(regex_set, regexes) => find_regex_hits(reader, regex_set, regexes, search_options)
+    }
+}
+
+// Matches contents that are already in memory against the provided patterns.
+fn find_hits_in(file_contents:&[u8], pattern_matcher:&PatternMatcher, search_options:&SearchOptions) -> FileHits {
+    match pattern_matcher {
+        PatternMatcher::Literal(literal_matcher) => find_literal_hits_in(file_contents, literal_matcher, search_options),
+        PatternMatcher::Regex(regex_set, regexes) => find_regex_hits_in(file_contents, regex_set, regexes, search_options)
+    }
+}
+
+// The decoder for the provided encoding, None if the raw bytes are matched.
+fn decoding_for(file_encoding:FileEncoding) -> Option<&'static Encoding> {
+    match file_encoding {
+        FileEncoding::Utf8 | FileEncoding::Auto => None,
+        FileEncoding::Utf16Le => Some(UTF_16LE),
+        FileEncoding::Utf16Be => Some(UTF_16BE)
+    }
+}
+
+// Reads the whole file and decodes it into UTF-8, returning the decoded contents along with the amount of bytes read.
+fn decode_file<R:Read>(queued_file:&String, mut file_stream:R, encoding:&'static Encoding) -> Result<(Vec<u8>, u64), SkipReason> {
+    let mut file_contents:Vec<u8> = Vec::new();
+
+    if let Err(error) = file_stream.read_to_end(&mut file_contents) {
+        return Err(read_error_reason(queued_file, false, error));
+    }
+
+    let (decoded_contents, had_errors) = encoding.decode_with_bom_removal(&file_contents);
+
+    if had_errors {
+        return Err(SkipReason::DecodeError(format!("Failed to decode file @ {} as {}", queued_file, encoding.name())));
+    }
+
+    Ok((decoded_contents.into_owned().into_bytes(), file_contents.len() as u64))
+}
+
+/// Reads the provided file as text, decoded the same way as when it was searched, so that match locations line up with its lines.
+pub fn read_file_text(file_path:&String, file_encoding:FileEncoding, decompress_gzip:bool) -> Result<String, String> {
+    let is_gzipped:bool = is_gzip_file(Path::new(file_path), decompress_gzip);
+
+    let mut file_stream:Box<dyn Read> = match open_file_reader(Path::new(file_path), is_gzipped, 0) {
+        Ok(stream) => stream,
+        Err(error) => return Err(format!("Failed to open stream to file @ {}, error: {:?}", file_path, error))
+    };
+
+    // A file whose encoding couldn't be detected was matched raw, so it's read raw here too.
+    let file_decoding:Option<&'static Encoding> = resolve_decoding(Path::new(file_path), file_encoding, is_gzipped, 0).unwrap_or(None);
+
+    let file_contents:Vec<u8> = match file_decoding {
+        Some(encoding) => decode_file(file_path, file_stream, encoding).map_err(|skip_reason| skip_reason.to_string())?.0,
+        None => {
+            let mut file_contents:Vec<u8> = Vec::new();
+
+            if let Err(error) = file_stream.read_to_end(&mut file_contents) {
+                return Err(format!("Failed to read data from file @ {}, error: {:?}", file_path, error));
+            }
+
+            file_contents
+        }
+    };
+
+    Ok(String::from_utf8_lossy(&file_contents).into_owned())
+}
+
+// Maps the file into memory, or returns why it couldn't be, in which case the file is read normally.
+fn map_file(queued_file:&String, retry_count:usize) -> Result<Mmap, String> {
+    let file_stream:File = match retry_io(retry_count, || File::open(queued_file)) {
+        Ok(stream) => stream,
+        Err(error) => return Err(format!("Failed to open stream to file @ {}, error: {:?}", queued_file, error))
+    };
+
+    match file_stream.metadata() {
+        Ok(file_metadata) if file_metadata.len() == 0 => return Err(String::from("Empty files can't be memory mapped")),
+        Ok(_) => (),
+        Err(error) => return Err(format!("Error when retrieving the file's metadata: {:?}", error))
+    };
+
+    // The mapping is only ever read, but truncating the file while it's being searched can crash the process, which is why -mmap is opt-in.
+    match unsafe { Mmap::map(&file_stream) } {
+        Ok(file_map) => Ok(file_map),
+        Err(error) => Err(format!("Couldn't memory map the file, error: {:?}", error))
+    }
+}
+
+// Whether at least progress_interval_ms have passed since the progress was last updated, in which case it's due again from now on.
+fn progress_due(last_progress:&mut Option<Instant>, progress_interval_ms:u64) -> bool {
+    let progress_due:bool = progress_interval_ms == 0 || last_progress.is_none_or(|last_progress| last_progress.elapsed() >= Duration::from_millis(progress_interval_ms));
+
+    if progress_due {
+        *last_progress = Some(Instant::now());
+    }
+
+    progress_due
+}
+
+// Redraws the search progress from the counters that the searching threads bump, until searching_receiver disconnects. The searching threads
+// never touch the progress bar themselves, so they never wait on each other to draw it, and what's drawn has no bearing on the results.
+// With an interval of 0 the searching threads send on the channel after every file instead, and each of those is redrawn.
+fn render_search_progress(search_progress:&ProgressBar, searched_count:&AtomicU64, total_bytes_read:&AtomicU64, searching_receiver:mpsc::Receiver<()>, progress_interval_ms:u64) {
+    loop {
+        search_progress.set_position(searched_count.load(Ordering::Relaxed));
+        search_progress.set_message(format!("{:.1} MB read", total_bytes_read.load(Ordering::Relaxed) as f64 / (1024.0 * 1024.0)));
+
+        let still_searching:bool = if progress_interval_ms == 0 {
+            searching_receiver.recv().is_ok()
+        } else {
+            !matches!(searching_receiver.recv_timeout(Duration::from_millis(progress_interval_ms)), Err(mpsc::RecvTimeoutError::Disconnected))
+        };
+
+        if !still_searching {
+            break;
+        }
+    }
+}
+
+// Counts the file as searched, and with an interval of 0 has the progress redrawn right away.
+fn note_searched(searched_count:&AtomicU64, progress_sender:Option<&mpsc::Sender<()>>) {
+    searched_count.fetch_add(1, Ordering::Relaxed);
+
+    if let Some(progress_sender) = progress_sender {
+        let _ = progress_sender.send(());
+    }
+}
+
+// Notes that the file was still searched, just not the way the options asked for.
+fn record_fallback(search_fallbacks:&Mutex<Vec<SkippedFile>>, queued_file:&str, fallback_reason:String) {
+    debug!("Fell back for {}: {}", queued_file, fallback_reason);
+
+    if let Ok(mut search_fallbacks) = search_fallbacks.lock() {
+        search_fallbacks.push(SkippedFile {
+            file_path:String::from(queued_file),
+            skip_reason:SkipReason::Fallback(fallback_reason),
+            skip_phase:SkipPhase::Searching
+        });
+    }
+}
+
+// With canonical_paths, reports a searched file under its canonical path, or under the path it was queued with if that can't be resolved.
+// Skipped files keep the path they were queued with, like the ones skipped while queuing.
+fn canonicalize_outcome(search_outcome:SearchOutcome, queued_file:&QueuedFile, search_options:&SearchOptions, search_fallbacks:&Mutex<Vec<SkippedFile>>) -> SearchOutcome {
+    if !search_options.canonical_paths || matches!(search_outcome, SearchOutcome::Skipped(_)) {
+        return search_outcome;
+    }
+
+    let resolved_path:&String = match &queued_file.archive_entry {
+        Some(archive_entry) => &archive_entry.archive_path,
+        None => &queued_file.file_path
+    };
+
+    let canonical_path:String = match fs::canonicalize(resolved_path) {
+        Ok(canonical_path) => match (canonical_path.to_str(), &queued_file.archive_entry) {
+            (Some(canonical_path), Some(archive_entry)) => format!("{}{}{}", canonical_path, ARCHIVE_ENTRY_SEPARATOR, archive_entry.entry_name),
+            (Some(canonical_path), None) => String::from(canonical_path),
+            (None, _) => {
+                record_fallback(search_fallbacks, &queued_file.file_path, format!("The canonical path isn't valid UTF-8, reported as queued: {}", canonical_path.display()));
+                return search_outcome;
+            }
+        },
+
+        Err(error) => {
+            record_fallback(search_fallbacks, &queued_file.file_path, format!("Couldn't canonicalize the path, reported as queued, error: {}", error));
+            return search_outcome;
+        }
+    };
+
+    match search_outcome {
+        SearchOutcome::Matched(mut matched_file) => {
+            matched_file.file_path = canonical_path;
+            SearchOutcome::Matched(matched_file)
+        }
+
+        SearchOutcome::Unmatched(_) => SearchOutcome::Unmatched(canonical_path),
+        SearchOutcome::Skipped(skipped_file) => SearchOutcome::Skipped(skipped_file)
+    }
+}
+
+// The decoder to read the file with, see resolve_decoding, recording a fallback if -enc auto couldn't tell.
+fn search_decoding(queued_file:&String, search_options:&SearchOptions, is_gzipped:bool, search_fallbacks:&Mutex<Vec<SkippedFile>>) -> Option<&'static Encoding> {
+    match resolve_decoding(Path::new(queued_file), search_options.encoding, is_gzipped, search_options.retry_count) {
+        Ok(file_decoding) => file_decoding,
+        Err(fallback_reason) => {
+            record_fallback(search_fallbacks, queued_file, fallback_reason);
+            None
+        }
+    }
+}
+
+fn read_error_reason(queued_file:&String, is_gzipped:bool, error:io::Error) -> SkipReason {
+    if is_gzipped {
+        SkipReason::IoError(format!("Failed to decompress gzip file @ {}, error: {:?}", queued_file, error))
+    } else {
+        SkipReason::IoError(format!("Failed to read data from file @ {}, error: {:?}", queued_file, error))
+    }
+}
+
+fn search_file(queued_entry:&QueuedFile, search_options:&SearchOptions, pattern_matcher:&PatternMatcher, total_bytes_read:&AtomicU64, search_fallbacks:&Mutex<Vec<SkippedFile>>) -> SearchOutcome {
+    let queued_file:&String = &queued_entry.file_path;
+    let path_hits:Vec<PatternHit> = find_path_hits(queued_entry, pattern_matcher, search_options);
+
+    // Only the path matters, so the file is never opened.
+    if search_options.match_target == MatchTarget::Paths {
+        let file_hits = FileHits { pattern_hits:Vec::new(), is_binary:false, bytes_read:0, match_cap_reached:false, first_match_sample:None, line_too_long:false, timed_out:false };
+        return classify_file_hits(queued_entry, file_hits, path_hits, search_options, total_bytes_read);
+    }
+
+    // Archive entries are decompressed into memory as a whole, the same way the pipelined readers load every file.
+    if queued_entry.archive_entry.is_some() {
+        return match load_file(queued_entry, search_options, search_fallbacks) {
+            Ok(loaded_file) => {
+                let mut file_hits:FileHits = find_hits_in(loaded_file.file_contents.as_bytes(), pattern_matcher, search_options);
+                file_hits.bytes_read = loaded_file.bytes_read;
+                classify_file_hits(queued_entry, file_hits, path_hits, search_options, total_bytes_read)
+            }
+
+            Err(skipped_file) => SearchOutcome::Skipped(skipped_file)
+        };
+    }
+
+    let is_gzipped:bool = is_gzip_file(Path::new(queued_file), search_options.decompress_gzip);
+
+    let file_decoding:Option<&'static Encoding> = search_decoding(queued_file, search_options, is_gzipped, search_fallbacks);
+
+    // Only raw bytes can be matched in place, gzipped and UTF-16 files have to be decoded into a buffer regardless.
+    if search_options.memory_map && !is_gzipped && file_decoding.is_none() {
+        match map_file(queued_file, search_options.retry_count) {
+            Ok(file_map) => return classify_file_hits(queued_entry, find_hits_in(&file_map, pattern_matcher, search_options), path_hits, search_options, total_bytes_read),
+            Err(fallback_reason) => record_fallback(search_fallbacks, queued_file, fallback_reason)
+        }
+    }
+
+    let file_stream:Box<dyn Read> = match open_file_reader(Path::new(queued_file), is_gzipped, search_options.retry_count) {
+        Ok(stream) => stream,
+        Err(error) => {
+            let skipped_file = SkippedFile {
+                file_path:queued_file.clone(),
+                skip_reason:SkipReason::IoError(format!("Failed to open stream to file @ {}, error: {:?}", queued_file, error)),
+                skip_phase:SkipPhase::Searching
+            };
+
+            return SearchOutcome::Skipped(skipped_file);
+        }
+    };
+
+    // UTF-16 files are decoded up front, the byte offsets of their matches then refer to the decoded contents.
+    let file_hits_result:io::Result<FileHits> = match file_decoding {
+        Some(encoding) => {
+            let (decoded_contents, file_size) = match decode_file(queued_file, file_stream, encoding) {
+                Ok(decoded_file) => decoded_file,
+                Err(skip_reason) => {
+                    let skipped_file = SkippedFile {
+                        file_path:queued_file.clone(),
+                        skip_reason,
+                        skip_phase:SkipPhase::Searching
+                    };
+
+                    return SearchOutcome::Skipped(skipped_file);
+                }
+            };
+
+            let mut file_hits:FileHits = find_hits_in(&decoded_contents, pattern_matcher, search_options);
+            file_hits.bytes_read = file_size;
+            Ok(file_hits)
+        }
+
+        None => find_hits(file_stream, pattern_matcher, search_options)
+    };
+
+    let file_hits:FileHits = match file_hits_result {
+        Ok(file_hits) => file_hits,
+        Err(error) => {
+            let skipped_file = SkippedFile {
+                file_path:queued_file.clone(),
+                skip_reason:read_error_reason(queued_file, is_gzipped, error),
+                skip_phase:SkipPhase::Searching
+            };
+
+            return SearchOutcome::Skipped(skipped_file);
+        }
+    };
+
+    classify_file_hits(queued_entry, file_hits, path_hits, search_options, total_bytes_read)
+}
+
+// With MatchTarget::Paths or PathsAndContents, the matches within the file's path below the directory it was found in.
+fn find_path_hits(queued_entry:&QueuedFile, pattern_matcher:&PatternMatcher, search_options:&SearchOptions) -> Vec<PatternHit> {
+    if search_options.match_target == MatchTarget::Contents {
+        return Vec::new();
+    }
+
+    let relative_path:&Path = path_below_root(Path::new(&queued_entry.file_path), &search_options.directories);
+    find_hits_in(relative_path.to_string_lossy().as_bytes(), pattern_matcher, search_options).pattern_hits
+}
+
+// With max_total_bytes, whether enough was read already that the files that are left should be skipped. The files being searched
+// at the time are still finished, so slightly more than the budget can end up read.
+fn budget_exhausted(search_options:&SearchOptions, total_bytes_read:&AtomicU64) -> bool {
+    search_options.max_total_bytes > 0 && total_bytes_read.load(Ordering::SeqCst) >= search_options.max_total_bytes
+}
+
+fn budget_skip(queued_file:&QueuedFile) -> SkippedFile {
+    SkippedFile {
+        file_path:queued_file.file_path.clone(),
+        skip_reason:SkipReason::BudgetExhausted,
+        skip_phase:SkipPhase::Searching
+    }
+}
+
+// With max_matched_files, whether enough files matched already that the files that are left should be skipped, see budget_exhausted.
+fn enough_files_matched(search_options:&SearchOptions, matched_count:&AtomicUsize) -> bool {
+    search_options.max_matched_files > 0 && matched_count.load(Ordering::SeqCst) >= search_options.max_matched_files
+}
+
+fn enough_matched_skip(queued_file:&QueuedFile) -> SkippedFile {
+    SkippedFile {
+        file_path:queued_file.file_path.clone(),
+        skip_reason:SkipReason::EnoughMatched,
+        skip_phase:SkipPhase::Searching
+    }
+}
+
+// Everything the pipeline's threads share while searching.
+struct SearchState<'a> {
+    search_options:&'a SearchOptions,
+    pattern_matcher:&'a PatternMatcher,
+    total_bytes_read:&'a AtomicU64,
+    search_fallbacks:&'a Mutex<Vec<SkippedFile>>,
+    searched_count:&'a AtomicU64,
+    matched_count:&'a AtomicUsize,
+    progress_sender:Option<&'a mpsc::Sender<()>>
+}
+
+// A file's contents, read in full by a reader thread, along with how many bytes were read from the disk for them.
+struct LoadedFile {
+    file_contents:LoadedContents,
+    bytes_read:u64
+}
+
+enum LoadedContents {
+    Buffered(Vec<u8>),
+    Mapped(Mmap)
+}
+
+impl LoadedContents {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            LoadedContents::Buffered(file_contents) => file_contents,
+            LoadedContents::Mapped(file_map) => file_map
+        }
+    }
+}
+
+// The reading half of search_file, which decodes and memory maps the file the same way, but reads it into memory rather than streaming it.
+fn load_file(queued_entry:&QueuedFile, search_options:&SearchOptions, search_fallbacks:&Mutex<Vec<SkippedFile>>) -> Result<LoadedFile, SkippedFile> {
+    let queued_file:&String = &queued_entry.file_path;
+
+    if let Some(archive_entry) = &queued_entry.archive_entry {
+        return load_archive_entry(queued_entry, archive_entry, search_options).map_err(|skip_reason| {
+            SkippedFile { file_path:queued_file.clone(), skip_reason, skip_phase:SkipPhase::Searching }
+        });
+    }
+
+    let is_gzipped:bool = is_gzip_file(Path::new(queued_file), search_options.decompress_gzip);
+
+    let file_decoding:Option<&'static Encoding> = search_decoding(queued_file, search_options, is_gzipped, search_fallbacks);
+
+    if search_options.memory_map && !is_gzipped && file_decoding.is_none() {
+        match map_file(queued_file, search_options.retry_count) {
+            Ok(file_map) => return Ok(LoadedFile { bytes_read:file_map.len() as u64, file_contents:LoadedContents::Mapped(file_map) }),
+            Err(fallback_reason) => record_fallback(search_fallbacks, queued_file, fallback_reason)
+        }
+    }
+
+    let mut file_stream:Box<dyn Read> = match open_file_reader(Path::new(queued_file), is_gzipped, search_options.retry_count) {
+        Ok(stream) => stream,
+        Err(error) => return Err(SkippedFile {
+            file_path:queued_file.clone(),
+            skip_reason:SkipReason::IoError(format!("Failed to open stream to file @ {}, error: {:?}", queued_file, error)),
+            skip_phase:SkipPhase::Searching
+        })
+    };
+
+    if let Some(encoding) = file_decoding {
+        return match decode_file(queued_file, file_stream, encoding) {
+            Ok((decoded_contents, file_size)) => Ok(LoadedFile { file_contents:LoadedContents::Buffered(decoded_contents), bytes_read:file_size }),
+            Err(skip_reason) => Err(SkippedFile { file_path:queued_file.clone(), skip_reason, skip_phase:SkipPhase::Searching })
+        };
+    }
+
+    let mut file_contents:Vec<u8> = Vec::new();
+
+    match file_stream.read_to_end(&mut file_contents) {
+        Ok(bytes_read) => Ok(LoadedFile { file_contents:LoadedContents::Buffered(file_contents), bytes_read:bytes_read as u64 }),
+        Err(error) => Err(SkippedFile { file_path:queued_file.clone(), skip_reason:read_error_reason(queued_file, is_gzipped, error), skip_phase:SkipPhase::Searching })
+    }
+}
+
+// Searches the queued files with separate reader and worker threads, which hand the files over through a bounded channel, so that at most a couple
+// of files per worker wait in memory. The outcomes are put back into queue order afterwards, so the results don't depend on thread timing.
+fn search_pipelined<F>(queued_files:&[QueuedFile], search_state:&SearchState, on_searched:&F) -> Vec<SearchOutcome> where F:Fn(&SearchOutcome) + Sync {
+    let search_options:&SearchOptions = search_state.search_options;
+
+    let reader_count:usize = search_options.reader_threads.max(1);
+    let worker_count:usize = if search_options.worker_threads > 0 {
+        search_options.worker_threads
+    } else {
+        thread::available_parallelism().map(|core_count| core_count.get()).unwrap_or(1)
+    };
+
+    let (loaded_sender, loaded_receiver) = mpsc::sync_channel::<(usize, Result<LoadedFile, SkippedFile>)>(worker_count * 2);
+    let loaded_receiver:Mutex<mpsc::Receiver<(usize, Result<LoadedFile, SkippedFile>)>> = Mutex::new(loaded_receiver);
+    let next_file:AtomicUsize = AtomicUsize::new(0);
+
+    let mut indexed_outcomes:Vec<(usize, SearchOutcome)> = thread::scope(|thread_scope| {
+        for _ in 0..reader_count {
+            let loaded_sender = loaded_sender.clone();
+            let next_file:&AtomicUsize = &next_file;
+
+            thread_scope.spawn(move || {
+                loop {
+                    let file_index:usize = next_file.fetch_add(1, Ordering::SeqCst);
+
+                    let queued_file:&QueuedFile = match queued_files.get(file_index) {
+                        Some(queued_file) => queued_file,
+                        None => break
+                    };
+
+                    let loaded_file:Result<LoadedFile, SkippedFile> = if budget_exhausted(search_options, search_state.total_bytes_read) {
+                        Err(budget_skip(queued_file))
+                    } else if enough_files_matched(search_options, search_state.matched_count) {
+                        Err(enough_matched_skip(queued_file))
+                    } else {
+                        load_file(queued_file, search_options, search_state.search_fallbacks)
+                    };
+
+                    // A send only fails once every worker is gone, which only happens if they panicked.
+                    if loaded_sender.send((file_index, loaded_file)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // The channel closes once every reader dropped its sender, which is how the workers know there's nothing left.
+        drop(loaded_sender);
+
+        let worker_handles:Vec<thread::ScopedJoinHandle<Vec<(usize, SearchOutcome)>>> = (0..worker_count).map(|_| thread_scope.spawn(|| {
+            let mut worker_outcomes:Vec<(usize, SearchOutcome)> = Vec::new();
+
+            loop {
+                let received = match loaded_receiver.lock() {
+                    Ok(loaded_receiver) => loaded_receiver.recv(),
+                    Err(_) => break
+                };
+
+                let (file_index, loaded_file) = match received {
+                    Ok(received) => received,
+                    Err(_) => break
+                };
+
+                let queued_file:&QueuedFile = &queued_files[file_index];
+
+                let search_outcome:SearchOutcome = match loaded_file {
+                    Ok(loaded_file) => {
+                        let mut file_hits:FileHits = find_hits_in(loaded_file.file_contents.as_bytes(), search_state.pattern_matcher, search_options);
+                        file_hits.bytes_read = loaded_file.bytes_read;
+
+                        let path_hits:Vec<PatternHit> = find_path_hits(queued_file, search_state.pattern_matcher, search_options);
+                        let search_outcome:SearchOutcome = classify_file_hits(queued_file, file_hits, path_hits, search_options, search_state.total_bytes_read);
+                        canonicalize_outcome(search_outcome, queued_file, search_options, search_state.search_fallbacks)
+                    }
+
+                    Err(skipped_file) => SearchOutcome::Skipped(skipped_file)
+                };
+
+                if let SearchOutcome::Matched(_) = search_outcome {
+                    search_state.matched_count.fetch_add(1, Ordering::SeqCst);
+                }
+
+                on_searched(&search_outcome);
+                note_searched(search_state.searched_count, search_state.progress_sender);
+                worker_outcomes.push((file_index, search_outcome));
+            }
+
+            worker_outcomes
+        })).collect();
+
+        // A worker's panic is passed on rather than silently losing its outcomes.
+        worker_handles.into_iter().flat_map(|worker_handle| worker_handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect()
+    });
+
+    indexed_outcomes.sort_by_key(|&(file_index, _)| file_index);
+    indexed_outcomes.into_iter().map(|(_, search_outcome)| search_outcome).collect()
+}
+
+// Turns the matches found within a file, and within its path, into its outcome.
+fn classify_file_hits(queued_file:&QueuedFile, file_hits:FileHits, path_hits:Vec<PatternHit>, search_options:&SearchOptions, total_bytes_read:&AtomicU64) -> SearchOutcome {
+    total_bytes_read.fetch_add(file_hits.bytes_read, Ordering::SeqCst);
+
+    if file_hits.timed_out {
+        let skipped_file = SkippedFile {
+            file_path:queued_file.file_path.clone(),
+            skip_reason:SkipReason::MatchTimeout { limit_ms:search_options.match_timeout_ms },
+            skip_phase:SkipPhase::Searching
+        };
+
+        return SearchOutcome::Skipped(skipped_file);
+    }
+
+    if file_hits.line_too_long {
+        let skipped_file = SkippedFile {
+            file_path:queued_file.file_path.clone(),
+            skip_reason:SkipReason::LineTooLong { limit:search_options.max_line_length },
+            skip_phase:SkipPhase::Searching
+        };
+
+        return SearchOutcome::Skipped(skipped_file);
+    }
+
+    let mut matched_patterns:Vec<String> = Vec::new();
+    let mut match_locations:Vec<MatchLocation> = Vec::new();
+
+    let match_count:usize = path_hits.len() + file_hits.pattern_hits.len();
+
+    // The path comes first, its matches have no line number.
+    for path_hit in path_hits {
+        let pattern_as_string:&String = pattern_at(search_options, path_hit.pattern_index);
+
+        if !matched_patterns.contains(pattern_as_string) {
+            matched_patterns.push(pattern_as_string.clone());
+        }
+
+        match_locations.push(MatchLocation {
+            pattern:pattern_as_string.clone(),
+            byte_offset:path_hit.byte_offset,
+            match_length:path_hit.match_length,
+            line_number:None
+        });
+    }
+
+    // With null_data, the line numbers of the hits are 1-based record numbers, and the patterns found in every record are collected.
+    let mut record_patterns:BTreeMap<usize, HashSet<usize>> = BTreeMap::new();
+
+    for pattern_hit in file_hits.pattern_hits {
+        let pattern_as_string:&String = pattern_at(search_options, pattern_hit.pattern_index);
+
+        if !matched_patterns.contains(pattern_as_string) {
+            matched_patterns.push(pattern_as_string.clone());
+        }
+
+        if search_options.null_data {
+            record_patterns.entry(pattern_hit.line_number - 1).or_default().insert(pattern_hit.pattern_index);
+        }
+
+        // Line numbers are meaningless in binary files, and aren't counted with null_data.
+        match_locations.push(MatchLocation {
+            pattern:pattern_as_string.clone(),
+            byte_offset:pattern_hit.byte_offset,
+            match_length:pattern_hit.match_length,
+            line_number:if file_hits.is_binary || search_options.null_data { None } else { Some(pattern_hit.line_number) }
+        });
+    }
+
+    let matched_records:Option<Vec<usize>> = if search_options.null_data {
+        Some(record_patterns.into_iter().filter(|(_, found_patterns)| {
+            !search_options.require_all_patterns || found_patterns.len() == pattern_count(search_options)
+        }).map(|(record_index, _)| record_index).collect())
+    } else {
+        None
+    };
+
+    // With -all, a file that's missing any of the patterns is considered unmatched, or with -null-data one that has no record holding them all.
+    // Reaching -maxmatches counts as a match even with -all, since the rest of the file was never searched.
+    let file_matched:bool = if file_hits.match_cap_reached {
+        true
+    } else if let Some(matched_records) = &matched_records {
+        !matched_records.is_empty() && match_count >= search_options.min_matches
+    } else if search_options.require_all_patterns {
+        matched_patterns.len() == pattern_count(search_options) && match_count >= search_options.min_matches
+    } else {
+        !matched_patterns.is_empty() && match_count >= search_options.min_matches
+    };
+
+    if file_matched {
+        let score:Option<u64> = if search_options.score_matches {
+            Some(match_locations.iter().map(|match_location| search_options.pattern_weights.get(&match_location.pattern).cloned().unwrap_or(1)).sum())
+        } else {
+            None
+        };
+
+        let matched_file = MatchedFile {
+            file_path:queued_file.file_path.clone(),
+            matched_patterns,
+            match_locations,
+            match_count,
+            match_cap_reached:file_hits.match_cap_reached,
+            file_size:queued_file.file_size,
+            modified_seconds:queued_file.modified_time.and_then(|modified_time| modified_time.duration_since(SystemTime::UNIX_EPOCH).ok()).map(|modified_time| modified_time.as_secs()),
+            first_match_sample:file_hits.first_match_sample,
+            matched_records,
+            score
+        };
+
+        SearchOutcome::Matched(matched_file)
+    } else {
+        SearchOutcome::Unmatched(queued_file.file_path.clone())
+    }
+}
+
+/// A run of consecutive lines that a replacement changes, see FileReplacement.
+pub struct ReplacedLines {
+    /// The 1-based line number of the first line.
+    pub line_number:usize,
+
+    /// The lines as they are, and as they'd be after replacing, lossily decoded and without the final newline.
+    pub original_lines:String,
+    pub replaced_lines:String
+}
+
+/// The replacement of every match within a single file, see plan_replacements.
+pub struct FileReplacement {
+    /// The absolute path of the file.
+    pub file_path:String,
+
+    /// How many matches are replaced.
+    pub replacement_count:usize,
+
+    /// The lines that change, in the order they're in.
+    pub replaced_lines:Vec<ReplacedLines>,
+
+    /// The whole contents of the file after replacing.
+    pub replaced_contents:Vec<u8>
+}
+
+/// Works out how the contents of every matched file change when their matches are replaced with replacement, without writing anything.
+/// The files are read again and matched the way search_options describe, except that every match is replaced, regardless of presence_only,
+/// stop_at_first or overlapping. In regex_mode, $1 or ${name} in the replacement insert a capture group of the regex that matched, and $$ a
+/// literal $. Matches of literal_patterns and hex_patterns, and every match outside of regex_mode, are replaced with the replacement exactly as it is.
+///
+/// Only raw contents can be rewritten, so the encoding and decompress_gzip are ignored. Files that look binary are skipped unless allow_binary
+/// is set, as are files that can't be read or no longer match, which are returned separately. Overlapping matches of different patterns
+/// are replaced by whichever starts first.
+pub fn plan_replacements(matched_files:&[MatchedFile], search_options:&SearchOptions, replacement:&str, allow_binary:bool) -> Result<(Vec<FileReplacement>, Vec<SkippedFile>), String> {
+    let mut replace_options:SearchOptions = search_options.clone();
+    replace_options.presence_only = false;
+    replace_options.stop_at_first = false;
+    replace_options.overlapping = false;
+    replace_options.capture_sample = false;
+
+    let pattern_matcher:PatternMatcher = build_search_matcher(&replace_options)?;
+
+    let mut file_replacements:Vec<FileReplacement> = Vec::new();
+    let mut skipped_files:Vec<SkippedFile> = Vec::new();
+
+    for matched_file in matched_files {
+        let file_contents:Vec<u8> = match retry_io(replace_options.retry_count, || fs::read(&matched_file.file_path)) {
+            Ok(file_contents) => file_contents,
+            Err(error) => {
+                let skipped_file = SkippedFile {
+                    file_path:matched_file.file_path.clone(),
+                    skip_reason:SkipReason::IoError(format!("Couldn't read the file to replace its matches, error: {:?}", error)),
+                    skip_phase:SkipPhase::Replacing
+                };
+
+                skipped_files.push(skipped_file);
+                continue;
+            }
+        };
+
+        if !allow_binary && is_binary_sample(&file_contents[..file_contents.len().min(BINARY_SAMPLE_SIZE)]) {
+            let skipped_file = SkippedFile {
+                file_path:matched_file.file_path.clone(),
+                skip_reason:SkipReason::BinaryDetected,
+                skip_phase:SkipPhase::Replacing
+            };
+
+            skipped_files.push(skipped_file);
+            continue;
+        }
+
+        let mut pattern_hits:Vec<PatternHit> = find_hits_in(&file_contents, &pattern_matcher, &replace_options).pattern_hits;
+        pattern_hits.sort_by_key(|pattern_hit| pattern_hit.byte_offset);
+
+        // Triples of (start, end, replaced bytes), which mustn't overlap.
+        let mut replaced_spans:Vec<(usize, usize, Vec<u8>)> = Vec::new();
+
+        for pattern_hit in &pattern_hits {
+            let match_end:usize = pattern_hit.byte_offset + pattern_hit.match_length;
+
+            if replaced_spans.last().is_some_and(|&(_, previous_end, _)| pattern_hit.byte_offset < previous_end) {
+                continue;
+            }
+
+            let mut replaced_bytes:Vec<u8> = Vec::new();
+
+            match &pattern_matcher {
+                PatternMatcher::Regex(_, regexes) if pattern_hit.pattern_index < replace_options.patterns.len() => {
+                    match regexes[pattern_hit.pattern_index].captures_at(&file_contents, pattern_hit.byte_offset) {
+                        Some(regex_captures) => regex_captures.expand(replacement.as_bytes(), &mut replaced_bytes),
+                        None => replaced_bytes.extend_from_slice(replacement.as_bytes())
+                    }
+                }
+
+                _ => replaced_bytes.extend_from_slice(replacement.as_bytes())
+            }
+
+            replaced_spans.push((pattern_hit.byte_offset, match_end, replaced_bytes));
+        }
+
+        if replaced_spans.is_empty() {
+            let skipped_file = SkippedFile {
+                file_path:matched_file.file_path.clone(),
+                skip_reason:SkipReason::NoLongerMatches,
+                skip_phase:SkipPhase::Replacing
+            };
+
+            skipped_files.push(skipped_file);
+            continue;
+        }
+
+        let mut replaced_contents:Vec<u8> = Vec::with_capacity(file_contents.len());
+        let mut copied_up_to:usize = 0;
+
+        for (span_start, span_end, replaced_bytes) in &replaced_spans {
+            replaced_contents.extend_from_slice(&file_contents[copied_up_to..*span_start]);
+            replaced_contents.extend_from_slice(replaced_bytes);
+            copied_up_to = *span_end;
+        }
+
+        replaced_contents.extend_from_slice(&file_contents[copied_up_to..]);
+
+        file_replacements.push(FileReplacement {
+            file_path:matched_file.file_path.clone(),
+            replacement_count:replaced_spans.len(),
+            replaced_lines:group_replaced_lines(&file_contents, &replaced_spans),
+            replaced_contents
+        });
+    }
+
+    Ok((file_replacements, skipped_files))
+}
+
+// Groups the replaced spans by the lines they're on, spans that share a line, or that reach across several, end up in the same group.
+fn group_replaced_lines(file_contents:&[u8], replaced_spans:&[(usize, usize, Vec<u8>)]) -> Vec<ReplacedLines> {
+    // The offset of the newline that ends the line the provided offset is on, or the end of the file.
+    let line_end = |byte_offset:usize| -> usize {
+        file_contents[byte_offset..].iter().position(|&byte| byte == b'\n').map_or(file_contents.len(), |newline_offset| byte_offset + newline_offset)
+    };
+
+    let mut replaced_lines:Vec<ReplacedLines> = Vec::new();
+
+    let mut line_number:usize = 1;
+    let mut counted_up_to:usize = 0;
+    let mut span_index:usize = 0;
+
+    while let Some((first_start, _, _)) = replaced_spans.get(span_index) {
+        let lines_start:usize = file_contents[..*first_start].iter().rposition(|&byte| byte == b'\n').map_or(0, |newline_offset| newline_offset + 1);
+        let mut lines_end:usize = *first_start;
+
+        let mut replaced_bytes:Vec<u8> = Vec::new();
+        let mut copied_up_to:usize = lines_start;
+
+        // Every span that starts within the lines so far changes them too, and extends them up to the end of the line it ends on.
+        while let Some((span_start, span_end, span_bytes)) = replaced_spans.get(span_index).filter(|(span_start, _, _)| *span_start <= lines_end) {
+            replaced_bytes.extend_from_slice(&file_contents[copied_up_to..*span_start]);
+            replaced_bytes.extend_from_slice(span_bytes);
+            copied_up_to = *span_end;
+
+            lines_end = lines_end.max(line_end(*span_end));
+            span_index += 1;
+        }
+
+        replaced_bytes.extend_from_slice(&file_contents[copied_up_to..lines_end]);
+
+        line_number += count_lines(&file_contents[counted_up_to..lines_start], b'\n');
+        counted_up_to = lines_start;
+
+        replaced_lines.push(ReplacedLines {
+            line_number,
+            original_lines:String::from_utf8_lossy(&file_contents[lines_start..lines_end]).into_owned(),
+            replaced_lines:String::from_utf8_lossy(&replaced_bytes).into_owned()
+        });
+    }
+
+    replaced_lines
+}
+
+/// A synthetic set of files and the patterns planted in them, see generate_corpus.
+pub struct SyntheticCorpus {
+    /// The planted patterns, every one is a word that the generated filler text can't contain.
+    pub patterns:Vec<String>,
+
+    /// The contents of every generated file, lines of lowercase words.
+    pub files:Vec<Vec<u8>>
+}
+
+// The size of every generated file but the last, which holds whatever is left over.
+const CORPUS_FILE_SIZE:usize = 1024 * 1024;
+
+// About one word in this many is a planted pattern.
+const CORPUS_PATTERN_RARITY:u64 = 2000;
+
+// A splitmix64 generator, which is plenty random for filler text and makes the corpus depend on nothing but the seed.
+struct CorpusRng {
+    state:u64
+}
+
+impl CorpusRng {
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut mixed:u64 = self.state;
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        mixed ^ (mixed >> 31)
+    }
+
+    // A number in 0..bound, the bias is irrelevant for bounds this small.
+    fn below(&mut self, bound:u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Generates about total_size bytes of text split into 1 MiB files, with pattern_count distinct patterns planted throughout them.
+/// The same seed always produces the same corpus, so that benchmark runs can be compared.
+///
+/// ```
+/// use content_search::generate_corpus;
+///
+/// let corpus = generate_corpus(7, 3 * 1024 * 1024, 4);
+///
+/// assert_eq!(corpus.patterns.len(), 4);
+/// assert_eq!(corpus.files.len(), 3);
+/// assert_eq!(corpus.files.iter().map(|file| file.len()).sum::<usize>(), 3 * 1024 * 1024);
+/// assert_eq!(corpus.files, generate_corpus(7, 3 * 1024 * 1024, 4).files);
+/// ```
+pub fn generate_corpus(seed:u64, total_size:u64, pattern_count:usize) -> SyntheticCorpus {
+    let mut corpus_rng = CorpusRng { state:seed };
+
+    // The filler words only use a-z, so the digits keep the patterns from ever occurring by chance.
+    let patterns:Vec<String> = (0..pattern_count).map(|pattern_index| {
+        let word:String = (0..6).map(|_| (b'a' + corpus_rng.below(26) as u8) as char).collect();
+        format!("{}{}", word, pattern_index)
+    }).collect();
+
+    let mut files:Vec<Vec<u8>> = Vec::new();
+    let mut remaining_size:usize = total_size as usize;
+
+    while remaining_size > 0 {
+        let file_size:usize = remaining_size.min(CORPUS_FILE_SIZE);
+        let mut file_contents:Vec<u8> = Vec::with_capacity(file_size + 16);
+
+        while file_contents.len() < file_size {
+            if !patterns.is_empty() && corpus_rng.below(CORPUS_PATTERN_RARITY) == 0 {
+                file_contents.extend_from_slice(patterns[corpus_rng.below(patterns.len() as u64) as usize].as_bytes());
+            } else {
+                let word_length:u64 = 2 + corpus_rng.below(9);
+                file_contents.extend((0..word_length).map(|_| b'a' + corpus_rng.below(26) as u8));
+            }
+
+            // Lines average around 80 bytes.
+            file_contents.push(if corpus_rng.below(12) == 0 { b'\n' } else { b' ' });
+        }
+
+        // The last word is cut off rather than the file overshooting, so the total size is exact.
+        file_contents.truncate(file_size);
+        remaining_size -= file_size;
+        files.push(file_contents);
+    }
+
+    SyntheticCorpus { patterns, files }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A fresh directory below the system's temp directory holding the provided files, each containing a line with hello. The test's name
+    // keeps the tests that run in parallel out of each other's way.
+    fn temp_tree(test_name:&str, file_paths:&[&str]) -> PathBuf {
+        let tree_root:PathBuf = env::temp_dir().join(format!("content-search-{}-{}", test_name, process::id()));
+        let _ = fs::remove_dir_all(&tree_root);
+
+        for file_path in file_paths {
+            let file_path:PathBuf = tree_root.join(file_path);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(&file_path, "hello\n").unwrap();
+        }
+
+        tree_root
+    }
+
+    fn tree_options(tree_root:&Path) -> SearchOptions {
+        SearchOptions {
+            directories:vec![tree_root.to_string_lossy().into_owned()],
+            ..SearchOptions::default()
+        }
+    }
+
+    #[test]
+    fn a_shared_matcher_matches_like_one_built_per_file() {
+        let tree_root:PathBuf = temp_tree("shared-matcher", &[]);
+        fs::create_dir_all(&tree_root).unwrap();
+
+        for file_number in 0..100 {
+            let file_contents:String = if file_number % 3 == 0 {
+                String::from("nothing to see here\n")
+            } else {
+                format!("first pat{} here\nthen pat{} and pat{} again\n", file_number % 50, (file_number * 7) % 50, file_number % 50)
+            };
+
+            fs::write(tree_root.join(format!("file{}.txt", file_number)), file_contents).unwrap();
+        }
+
+        let patterns:Vec<String> = (0..50).map(|pattern_number| format!("pat{}", pattern_number)).collect();
+        let search_results:SearchResults = perform_search(&SearchOptions { patterns:patterns.clone(), ..tree_options(&tree_root) }).unwrap();
+
+        let mut shared_matches:Vec<(String, Vec<String>)> = search_results.matched_files.iter().map(|matched_file| (matched_file.file_path.clone(), matched_file.matched_patterns.clone())).collect();
+
+        // The way every file used to be matched, with an automaton of its own.
+        let mut per_file_matches:Vec<(String, Vec<String>)> = Vec::new();
+
+        for file_number in 0..100 {
+            let file_path:PathBuf = tree_root.join(format!("file{}.txt", file_number));
+            let aho_corasick_search_alg:AhoCorasick = AhoCorasick::new(&patterns);
+            let mut matched_patterns:Vec<String> = Vec::new();
+
+            for matched_pattern in aho_corasick_search_alg.find_iter(&fs::read(&file_path).unwrap()) {
+                if !matched_patterns.contains(&patterns[matched_pattern.pattern()]) {
+                    matched_patterns.push(patterns[matched_pattern.pattern()].clone());
+                }
+            }
+
+            if !matched_patterns.is_empty() {
+                per_file_matches.push((file_path.to_string_lossy().into_owned(), matched_patterns));
+            }
+        }
+
+        shared_matches.sort();
+        per_file_matches.sort();
+
+        fs::remove_dir_all(&tree_root).unwrap();
+        assert_eq!(shared_matches.len(), 66);
+        assert_eq!(shared_matches, per_file_matches);
+    }
+
+    #[test]
+    fn case_insensitive_matches_report_the_typed_pattern() {
+        let tree_root:PathBuf = temp_tree("case-insensitive", &[]);
+        fs::create_dir_all(&tree_root).unwrap();
+        fs::write(tree_root.join("app.log"), "12:00 ERROR disk full\n").unwrap();
+
+        let search_error = |regex_mode:bool, case_insensitive:bool| perform_search(&SearchOptions {
+            patterns:vec![String::from("error")],
+            regex_mode,
+            case_insensitive,
+            ..tree_options(&tree_root)
+        }).unwrap();
+
+        let literal_results:SearchResults = search_error(false, true);
+        let regex_results:SearchResults = search_error(true, true);
+        let case_sensitive_results:SearchResults = search_error(false, false);
+
+        fs::remove_dir_all(&tree_root).unwrap();
+        assert_eq!(literal_results.matched_files.len(), 1);
+        assert_eq!(literal_results.matched_files[0].matched_patterns, vec!["error"]);
+        assert_eq!(regex_results.matched_files.len(), 1);
+        assert_eq!(regex_results.matched_files[0].matched_patterns, vec!["error"]);
+        assert!(case_sensitive_results.matched_files.is_empty());
+    }
+
+    #[test]
+    fn nul_bytes_mark_a_sample_as_binary() {
+        assert!(is_binary_sample(b"\x7fELF\x02\x01\x01\x00\x00"));
+        assert!(is_binary_sample(b"plain text with a \0 in it"));
+
+        assert!(!is_binary_sample(b"plain text\nacross lines\n"));
+        assert!(!is_binary_sample("UTF-8 text, caf\u{e9}".as_bytes()));
+        assert!(!is_binary_sample(b""));
+    }
+
+    #[test]
+    fn max_files_caps_the_queue() {
+        let file_paths:Vec<String> = (0..10).map(|file_number| format!("file{}.txt", file_number)).collect();
+        let tree_root:PathBuf = temp_tree("max-files", &file_paths.iter().map(String::as_str).collect::<Vec<&str>>());
+
+        let search_options = SearchOptions { patterns:vec![String::from("hello")], max_files:3, ..tree_options(&tree_root) };
+        let search_results:SearchResults = perform_search(&search_options).unwrap();
+
+        fs::remove_dir_all(&tree_root).unwrap();
+        assert_eq!(search_results.matched_files.len() + search_results.unmatched_files.len(), 3);
+    }
+
+    fn pattern_options(patterns:&[&str]) -> SearchOptions {
+        SearchOptions {
+            patterns:patterns.iter().map(|pattern| String::from(*pattern)).collect(),
+            show_progress:false,
+            ..SearchOptions::default()
+        }
+    }
+
+    #[test]
+    fn finds_a_match_straddling_the_chunk_boundary() {
+        let search_options:SearchOptions = pattern_options(&["needle"]);
+        let pattern_matcher:PatternMatcher = build_search_matcher(&search_options).unwrap();
+
+        let match_offset:usize = STREAM_CHUNK_SIZE - 2;
+        let mut file_contents:Vec<u8> = vec![b'x'; STREAM_CHUNK_SIZE * 2];
+
+        for newline_offset in [10, 1000, match_offset - 1] {
+            file_contents[newline_offset] = b'\n';
+        }
+
+        file_contents[match_offset..match_offset + 6].copy_from_slice(b"needle");
+
+        let file_hits:FileHits = find_hits(io::Cursor::new(file_contents), &pattern_matcher, &search_options).unwrap();
+
+        assert_eq!(file_hits.pattern_hits.len(), 1);
+        assert_eq!(file_hits.pattern_hits[0].byte_offset, match_offset);
+        assert_eq!(file_hits.pattern_hits[0].line_number, 4);
+    }
+
+    // The offsets of the whole word matches of cat when it's written over spaces at the provided offset, streamed a chunk at a time.
+    fn stream_cat_at(text_offset:usize, text:&[u8]) -> Vec<usize> {
+        let search_options = SearchOptions { whole_words:true, ..pattern_options(&["cat"]) };
+        let pattern_matcher:PatternMatcher = build_search_matcher(&search_options).unwrap();
+
+        let mut file_contents:Vec<u8> = vec![b' '; STREAM_CHUNK_SIZE * 2];
+        file_contents[text_offset..text_offset + text.len()].copy_from_slice(text);
+
+        let file_hits:FileHits = find_hits(io::Cursor::new(file_contents), &pattern_matcher, &search_options).unwrap();
+        file_hits.pattern_hits.iter().map(|pattern_hit| pattern_hit.byte_offset).collect()
+    }
+
+    #[test]
+    fn whole_words_see_the_byte_before_a_straddling_match() {
+        assert_eq!(stream_cat_at(STREAM_CHUNK_SIZE - 2, b"xcat"), Vec::<usize>::new());
+        assert_eq!(stream_cat_at(STREAM_CHUNK_SIZE - 2, b" cat"), vec![STREAM_CHUNK_SIZE - 1]);
+    }
+
+    #[test]
+    fn whole_words_see_the_byte_before_a_match_ending_a_chunk() {
+        assert_eq!(stream_cat_at(STREAM_CHUNK_SIZE - 4, b"xcat"), Vec::<usize>::new());
+        assert_eq!(stream_cat_at(STREAM_CHUNK_SIZE - 4, b" cat"), vec![STREAM_CHUNK_SIZE - 3]);
+    }
+
+    // The amount of matches of aa in aaaa, both when streamed and when already in memory.
+    fn count_aa_in_aaaa(search_options:&SearchOptions) -> (usize, usize) {
+        let pattern_matcher:PatternMatcher = build_search_matcher(search_options).unwrap();
+
+        let streamed_hits:FileHits = find_hits(io::Cursor::new(b"aaaa"), &pattern_matcher, search_options).unwrap();
+        let in_memory_hits:FileHits = find_hits_in(b"aaaa", &pattern_matcher, search_options);
+
+        (streamed_hits.pattern_hits.len(), in_memory_hits.pattern_hits.len())
+    }
+
+    #[test]
+    fn counts_overlapping_literal_matches() {
+        assert_eq!(count_aa_in_aaaa(&pattern_options(&["aa"])), (2, 2));
+        assert_eq!(count_aa_in_aaaa(&SearchOptions { overlapping:true, ..pattern_options(&["aa"]) }), (3, 3));
+    }
+
+    #[test]
+    fn counts_overlapping_regex_matches() {
+        assert_eq!(count_aa_in_aaaa(&SearchOptions { regex_mode:true, ..pattern_options(&["aa"]) }), (2, 2));
+        assert_eq!(count_aa_in_aaaa(&SearchOptions { regex_mode:true, overlapping:true, ..pattern_options(&["aa"]) }), (3, 3));
+    }
+
+    // Fails the first failures_left reads, then reads the contents.
+    struct FlakyReader {
+        failures_left:usize,
+        contents:io::Cursor<Vec<u8>>
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buffer:&mut [u8]) -> io::Result<usize> {
+            if self.failures_left > 0 {
+                self.failures_left -= 1;
+                return Err(io::Error::other("the file is locked"));
+            }
+
+            self.contents.read(buffer)
+        }
+    }
+
+    fn read_flaky(failure_count:usize, retry_count:usize) -> io::Result<Vec<u8>> {
+        let flaky_reader = FlakyReader { failures_left:failure_count, contents:io::Cursor::new(b"hello".to_vec()) };
+        let mut retrying_reader = RetryingReader { reader:flaky_reader, retry_count };
+
+        let mut file_contents:Vec<u8> = Vec::new();
+        retrying_reader.read_to_end(&mut file_contents)?;
+        Ok(file_contents)
+    }
+
+    #[test]
+    fn retries_reads_that_fail() {
+        assert_eq!(read_flaky(2, 2).unwrap(), b"hello");
+        assert_eq!(read_flaky(2, 3).unwrap(), b"hello");
+        assert_eq!(read_flaky(2, 1).unwrap_err().to_string(), "the file is locked");
+        assert!(read_flaky(1, 0).is_err());
+    }
+
+    #[test]
+    fn whole_words_stop_at_non_word_bytes() {
+        assert!(is_whole_word(b"foo bar", 0, 3, 0));
+        assert!(is_whole_word(b"bar foo", 4, 7, 0));
+        assert!(is_whole_word(b"foo", 0, 3, 0));
+        assert!(is_whole_word(b"(foo),", 1, 4, 0));
+        assert!(is_whole_word(b"a.foo-b", 2, 5, 0));
+
+        assert!(!is_whole_word(b"_foo", 1, 4, 0));
+        assert!(!is_whole_word(b"foo_", 0, 3, 0));
+        assert!(!is_whole_word(b"1foo", 1, 4, 0));
+        assert!(!is_whole_word(b"foo2", 0, 3, 0));
+        assert!(!is_whole_word(b"afoo", 1, 4, 0));
+
+        // Past the start of the file, the byte before the window is unknown, so it can't be a bound.
+        assert!(!is_whole_word(b"foo bar", 0, 3, 10));
+        assert!(is_whole_word(b" foo bar", 1, 4, 10));
+    }
+
+    #[test]
+    fn extensions_match_with_or_without_a_dot() {
+        assert!(has_any_extension(Path::new("src/main.rs"), &["rs"]));
+        assert!(has_any_extension(Path::new("src/main.rs"), &[".rs"]));
+        assert!(has_any_extension(Path::new("src/MAIN.RS"), &["rs"]));
+        assert!(has_any_extension(Path::new("src/main.rs"), &[".RS"]));
+        assert!(has_any_extension(Path::new("src/main.rs"), &["txt", "rs"]));
+
+        assert!(!has_any_extension(Path::new("src/main.rs"), &["txt"]));
+        assert!(!has_any_extension(Path::new("src/main.rsx"), &["rs"]));
+        assert!(!has_any_extension(Path::new("src/.rs"), &["rs"]));
+        assert!(!has_any_extension(Path::new("src/.rs"), &[".rs"]));
+        assert!(!has_any_extension(Path::new("src/Makefile"), &["rs"]));
+    }
+
+    // The searched files' paths below the tree's root, sorted and with / separators. Every file in a temp_tree holds hello, so it's searched
+    // for to find them all.
+    fn searched_below(tree_root:&Path, search_options:SearchOptions) -> Vec<String> {
+        let search_options = SearchOptions { patterns:vec![String::from("hello")], ..search_options };
+        let search_results:SearchResults = perform_search(&search_options).unwrap();
+
+        let mut searched_paths:Vec<String> = search_results.matched_files.iter().map(|matched_file| &matched_file.file_path).chain(search_results.unmatched_files.iter())
+            .map(|file_path| Path::new(file_path).strip_prefix(tree_root).unwrap().components().map(|component| component.as_os_str().to_string_lossy().into_owned()).collect::<Vec<String>>().join("/"))
+            .collect();
+
+        searched_paths.sort();
+        searched_paths
+    }
+
+    #[test]
+    fn depth_leaves_out_deeper_files() {
+        let tree_root:PathBuf = temp_tree("depth", &["top.txt", "a/one.txt", "a/b/two.txt", "a/b/c/three.txt"]);
+
+        let depth_options = SearchOptions { max_depth:Some(1), ..tree_options(&tree_root) };
+        let ignore_options = SearchOptions { max_depth:Some(1), respect_ignore:true, ..tree_options(&tree_root) };
+        let root_options = SearchOptions { max_depth:Some(0), ..tree_options(&tree_root) };
+
+        let depth_search:Vec<String> = searched_below(&tree_root, depth_options);
+        let ignore_search:Vec<String> = searched_below(&tree_root, ignore_options);
+        let root_search:Vec<String> = searched_below(&tree_root, root_options);
+
+        fs::remove_dir_all(&tree_root).unwrap();
+        assert_eq!(depth_search, vec!["a/one.txt", "top.txt"]);
+        assert_eq!(ignore_search, vec!["a/one.txt", "top.txt"]);
+        assert_eq!(root_search, vec!["top.txt"]);
+    }
+
+    #[test]
+    fn excluded_directories_are_never_searched() {
+        let tree_root:PathBuf = temp_tree("exclude-dir", &["top.txt", "src/lib.txt", "target/out.txt", "src/target/deep.txt", "src/targets/kept.txt"]);
+
+        let exclude_options = SearchOptions { excluded_directories:vec![String::from("target")], ..tree_options(&tree_root) };
+        let ignore_options = SearchOptions { excluded_directories:vec![String::from("target")], respect_ignore:true, ..tree_options(&tree_root) };
+
+        let exclude_search:Vec<String> = searched_below(&tree_root, exclude_options);
+        let ignore_search:Vec<String> = searched_below(&tree_root, ignore_options);
+
+        fs::remove_dir_all(&tree_root).unwrap();
+        assert_eq!(exclude_search, vec!["src/lib.txt", "src/targets/kept.txt", "top.txt"]);
+        assert_eq!(ignore_search, vec!["src/lib.txt", "src/targets/kept.txt", "top.txt"]);
+    }
+}
@@ -1,372 +1,2620 @@
-extern crate glob;
-use glob::glob;
+extern crate content_search;
+use content_search::{decode_hex_pattern, generate_corpus, parse_size, perform_search, perform_search_streaming, plan_replacements, search_patterns, search_stream, queue_files, read_file_text, FileEncoding, FileQueue, FileReplacement, JSON_SCHEMA_VERSION, MatchLocation, MatchTarget, MatchedFile, SearchOptions, SearchOutcome, SearchResults, SkipPhase, SkipReason, SkippedFile, SyntheticCorpus};
 
-extern crate aho_corasick;
-use aho_corasick::AhoCorasick;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+extern crate csv;
+extern crate toml;
+
+#[macro_use]
+extern crate log;
+use log::LevelFilter;
+
+extern crate env_logger;
+
+extern crate notify;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+extern crate termcolor;
+use termcolor::{Color, ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
 
 use std::io::prelude::*;
+use std::io::{self, BufWriter, IsTerminal};
 use std::fs::File;
+use std::cmp::Ordering;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::process;
+use std::path::{Path, PathBuf};
 use std::env;
+use std::time::{Duration, Instant};
 use std::fs;
 
-struct MatchedFile {
-    // The absolute path of the matched file.
-    file_path:String,
+// The object written to stdout when -json is provided.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    // Always the first field, see JSON_SCHEMA_VERSION.
+    schema_version:u32,
+
+    // Whether -v was provided, in which case the matched files are left out.
+    inverted:bool,
+
+    matched_count:usize,
+    skipped_count:usize,
+    unmatched_count:usize,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_files:Option<&'a Vec<MatchedFile>>,
 
-    // The list of patterns that matched.
-    matched_patterns:Vec<String>
+    skipped_files:&'a Vec<SkippedFile>,
+    unmatched_files:&'a Vec<String>
 }
 
-struct SkippedFile {
-    // Absolute path of the file that was skipped, can be "Unknown".
-    file_path:String,
+// A single line written to stdout when -ndjson is provided.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum NdjsonRecord<'a> {
+    Matched(&'a MatchedFile),
+
+    // Only written with -v, in place of the matched files.
+    Unmatched {
+        file_path:&'a str
+    },
+
+    // Always the last line, once the search is done.
+    Summary {
+        schema_version:u32,
+        inverted:bool,
+        matched_count:usize,
+        skipped_count:usize,
+        unmatched_count:usize
+    }
+}
 
-    // The reason that the file was skipped.
-    skip_reason:String
+// Where the NDJSON lines go, shared by the searching threads. Only the first write error is kept, every write after it is dropped.
+struct NdjsonStream {
+    output_stream:BufWriter<Box<dyn Write + Send>>,
+    write_result:io::Result<()>
 }
 
-struct SearchResults {
-    // Files that met the provided conditions, and matched one or more provided patterns.
-    matched_files:Vec<MatchedFile>,
+impl NdjsonStream {
+    fn write_record(&mut self, ndjson_record:&NdjsonRecord) {
+        if self.write_result.is_err() {
+            return;
+        }
 
-    // Files that were skipped for some reason.
-    skipped_files:Vec<SkippedFile>,
-    
-    // Candidate files that met the provided conditions, but didn't match any of the provided patterns.
-    unmatched_files:Vec<String>
+        // Flushing every line is what gets results out while the search is still running, the buffer only batches serde_json's small writes.
+        self.write_result = serde_json::to_writer(&mut self.output_stream, ndjson_record).map_err(io::Error::from)
+            .and_then(|_| writeln!(self.output_stream))
+            .and_then(|_| self.output_stream.flush());
+    }
 }
 
-fn perform_search(directory:&String, file_extensions:&Vec<String>, patterns:&Vec<String>, max_file_size:&u64, max_files:&usize) -> Result<SearchResults, String> {
-    let mut search_results = SearchResults {
-        matched_files:Vec::new(),
-        skipped_files:Vec::new(),
-        unmatched_files:Vec::new(),
+// Writes the line for a file as soon as it's been searched, if it's one of the selected files.
+fn write_ndjson_outcome(ndjson_stream:&Mutex<NdjsonStream>, search_outcome:&SearchOutcome, display_options:&DisplayOptions) {
+    let relative_file:MatchedFile;
+
+    let ndjson_record:NdjsonRecord = match search_outcome {
+        SearchOutcome::Matched(matched_file) if !display_options.invert_match && display_options.relative_roots.is_empty() => NdjsonRecord::Matched(matched_file),
+
+        SearchOutcome::Matched(matched_file) if !display_options.invert_match => {
+            relative_file = MatchedFile { file_path:String::from(display_path(&matched_file.file_path, display_options)), ..matched_file.clone() };
+            NdjsonRecord::Matched(&relative_file)
+        }
+
+        SearchOutcome::Unmatched(unmatched_file) if display_options.invert_match => NdjsonRecord::Unmatched { file_path:display_path(unmatched_file, display_options) },
+        _ => return
+    };
+
+    // A thread that panicked while holding the lock can't have left a half written line, since the line is written in one go.
+    let mut ndjson_stream = match ndjson_stream.lock() {
+        Ok(ndjson_stream) => ndjson_stream,
+        Err(poisoned) => poisoned.into_inner()
     };
 
-    let extensions_matter:bool = file_extensions.len() > 0;
-    let file_size_matters:bool = *max_file_size > 0;
-    let file_count_matters:bool = *max_files > 0;
+    ndjson_stream.write_record(&ndjson_record);
+}
 
-    let glob_pattern:String = if directory.ends_with("/") || directory.ends_with("\\") { directory.clone() + "**/*" } else { directory.clone() + "/**/*" };
+// Loaded automatically from the working directory when -config isn't given.
+const DEFAULT_CONFIG_PATH:&str = "contentsearch.toml";
 
-    let directory_entries = match glob(glob_pattern.as_str()) {
-        Ok(directory_entries) => directory_entries,
-        Err(error) => return Err(format!("Couldn't retrieve directory entries for the directory ({}), error: {:?}", directory, error))
+// Reads the default SearchOptions from a TOML file, any option it leaves out keeps its built-in default.
+fn read_config_file(config_path:&String) -> Result<SearchOptions, String> {
+    let config_contents:String = match fs::read_to_string(config_path) {
+        Ok(config_contents) => config_contents,
+        Err(error) => return Err(format!("Couldn't read the config file @ {}, error: {}", config_path, error))
     };
 
-    // List of queued files that will be searched for matching patterns.
-    let mut queued_files:Vec<String> = Vec::new();
+    match toml::from_str(&config_contents) {
+        Ok(search_options) => Ok(search_options),
+        Err(error) => Err(format!("The config file @ {} is malformed, error: {}", config_path, error))
+    }
+}
 
-    // Fill the queue with candidate files.
-    for (index, element) in directory_entries.enumerate() {
-        let path_obj = match element {
-            Ok(file_path) => file_path,
-            Err(error) => {
-                let skipped_file = SkippedFile { 
-                    file_path:String::from("Unknown"),
-                    skip_reason:format!("Skipped due to error when matching element: {:?}", error)
-                };
+// Reads search patterns from a file, one per line. Blank lines and lines starting with # are ignored.
+fn read_pattern_file(pattern_file_path:&String) -> Result<Vec<String>, String> {
+    let pattern_file_contents:String = match fs::read_to_string(pattern_file_path) {
+        Ok(pattern_file_contents) => pattern_file_contents,
+        Err(error) => return Err(format!("Couldn't read the pattern file @ {}, error: {}", pattern_file_path, error))
+    };
 
-                search_results.skipped_files.push(skipped_file);
-                continue;
-            }
-        };
+    Ok(pattern_file_contents.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect())
+}
 
-        // If the path points to a file, continue.
-        if path_obj.is_file() {
-            let absolute_file_path:String = match path_obj.to_str() {
-                Some(absolute_file_path) => String::from(absolute_file_path),
-                None => {
-                    let skipped_file = SkippedFile {
-                        file_path:String::from("Unknown"),
-                        skip_reason:format!("Couldn't convert the PathBuf into a string to get the absolute file path, presumably because the path is invalid UTF-8.")
-                    };
-
-                    search_results.skipped_files.push(skipped_file);
-                    continue;
-                }
-            };
+// Reads the paths to exclude from a file, one per line. Blank lines and lines starting with # are ignored.
+fn read_exclude_file(exclude_file_path:&String) -> Result<Vec<String>, String> {
+    let exclude_file_contents:String = match fs::read_to_string(exclude_file_path) {
+        Ok(exclude_file_contents) => exclude_file_contents,
+        Err(error) => return Err(format!("Couldn't read the exclude file @ {}, error: {}", exclude_file_path, error))
+    };
 
-            let file_size:u64 = match fs::metadata(&path_obj) {
-                Ok(file_metadata) => file_metadata.len(),
-                Err(error) => {
-                    let skipped_file = SkippedFile {
-                        file_path:absolute_file_path,
-                        skip_reason:format!("Error when retrieving the file's size: {:?}", error)
-                    };
+    Ok(exclude_file_contents.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect())
+}
 
-                    search_results.skipped_files.push(skipped_file);
-                    continue;
-                }
-            };
+// Splits the extension list given to -ext or -xext on : or , into trimmed extensions without their leading dot, dropping empty entries.
+fn parse_extension_list(extension_list:&str) -> Vec<String> {
+    let mut extensions:Vec<String> = Vec::new();
 
-            // If the amount of queued files exceeds the maximum, break and proceed with the search.
-            if file_count_matters && queued_files.len() > *max_files {
-                break;
-            }
+    for extension in extension_list.split([':', ',']) {
+        let extension:&str = extension.trim();
+        let extension:&str = extension.strip_prefix('.').unwrap_or(extension);
 
-            if extensions_matter && !file_extensions.iter().any(|file_extension| absolute_file_path.ends_with(file_extension)) {
-                let skipped_file = SkippedFile {
-                    file_path:absolute_file_path,
-                    skip_reason:format!("The file did not end with any of the provided extensions.")
-                };
+        if extension.is_empty() {
+            continue;
+        }
 
-                search_results.skipped_files.push(skipped_file);
-                continue;
-            }
+        extensions.push(String::from(extension));
+    }
 
-            // Proceed if the file size doesn't natter, or if it does matter and the file size is less than the provided maximum.
-            if !file_size_matters || (file_size_matters && file_size <= *max_file_size) {
-                queued_files.push(absolute_file_path);
+    extensions
+}
 
-            } else {
-                let skipped_file = SkippedFile {
-                    file_path:absolute_file_path,
-                    skip_reason:format!("The file exceeded the provided size ({} > {})", file_size, max_file_size)
-                };
+// The extensions that -lang expands to, for every language that has a preset, in alphabetical order so the list can be shown as it is.
+const LANGUAGE_PRESETS:&[(&str, &[&str])] = &[
+    ("c",        &["c", "h"]),
+    ("cpp",      &["cpp", "hpp", "cc", "h", "cxx", "hxx"]),
+    ("csharp",   &["cs"]),
+    ("go",       &["go"]),
+    ("java",     &["java"]),
+    ("kotlin",   &["kt", "kts"]),
+    ("markdown", &["md", "markdown"]),
+    ("python",   &["py", "pyi"]),
+    ("ruby",     &["rb"]),
+    ("rust",     &["rs"]),
+    ("shell",    &["sh", "bash", "zsh"]),
+    ("web",      &["js", "ts", "html", "css"])
+];
+
+// The extensions of the provided language's preset, compared case-insensitively, or an error listing the presets if there's none.
+fn language_extensions(language:&str) -> Result<Vec<String>, String> {
+    match LANGUAGE_PRESETS.iter().find(|(preset_name, _)| preset_name.eq_ignore_ascii_case(language.trim())) {
+        Some((_, preset_extensions)) => Ok(preset_extensions.iter().map(|extension| String::from(*extension)).collect()),
+        None => {
+            let preset_names:Vec<&str> = LANGUAGE_PRESETS.iter().map(|(preset_name, _)| *preset_name).collect();
+            Err(format!("There's no preset for the provided language ({}), the available presets are: {}", language, preset_names.join(", ")))
+        }
+    }
+}
 
-                search_results.skipped_files.push(skipped_file);
-                continue;
-            }
+// An extension that still contains whitespace can't match any file, so it's kept but warned about.
+fn warn_about_whitespace(extensions:&[String], flag:&str) {
+    for extension in extensions.iter().filter(|extension| extension.contains(char::is_whitespace)) {
+        warn!("The extension ({}) given to {} contains whitespace, so it won't match any file. Separate extensions with : or ,", extension, flag);
+    }
+}
+
+// Drops every pattern that was already given, warning about each, so that it's neither searched for twice nor counted twice in -tally.
+// A literal pattern duplicates a regular one only when both are matched literally, i.e. outside of -regex and -anchor.
+fn dedupe_patterns(search_options:&mut SearchOptions) {
+    let mut seen_patterns:HashSet<String> = HashSet::new();
+
+    search_options.patterns.retain(|pattern| {
+        let is_new:bool = seen_patterns.insert(pattern.clone());
+
+        if !is_new {
+            warn!("The pattern ({}) was given more than once, it's only searched for once.", pattern);
         }
-        
-        print!("Queueing files.. {} / {} Files have been queued..\r", queued_files.len(), index + 1);
+
+        is_new
+    });
+
+    if search_options.regex_mode || search_options.anchor_patterns {
+        seen_patterns.clear();
     }
 
-    println!("");
+    search_options.literal_patterns.retain(|literal_pattern| {
+        let is_new:bool = seen_patterns.insert(literal_pattern.clone());
 
-    let mut last_message_size:usize = 0;
+        if !is_new {
+            warn!("The literal pattern ({}) was given more than once, it's only searched for once.", literal_pattern);
+        }
 
-    for (index, queued_file) in queued_files.iter().enumerate() {
-        let relative_file_path:String = match queued_file.clone().split("\\").last() {
-            Some(relative_file_path) => String::from(relative_file_path),
-            None => String::from(queued_file)
-        };
+        is_new
+    });
 
-        let mut message = format!("[{} / {}] Searching through {} for patterns..", index + 1, queued_files.len(), relative_file_path);
+    // Hex patterns are compared by the bytes they stand for, so that DEAD duplicates dead.
+    let mut seen_hex_patterns:HashSet<String> = HashSet::new();
 
-        if message.len() < last_message_size {
-            message += " ".repeat(last_message_size - message.len()).as_str();
+    search_options.hex_patterns.retain(|hex_pattern| {
+        let is_new:bool = seen_hex_patterns.insert(hex_pattern.to_ascii_lowercase());
+
+        if !is_new {
+            warn!("The hex pattern ({}) was given more than once, it's only searched for once.", hex_pattern);
         }
 
-        last_message_size = message.len();
-        
-        print!("{}\r", message);
+        is_new
+    });
+}
 
-        let mut file_stream = match File::open(&queued_file) {
-            Ok(stream) => stream,
-            Err(error) => {
-                let skipped_file = SkippedFile {
-                    file_path:queued_file.clone(),
-                    skip_reason:format!("Failed to open stream to file @ {}, error: {:?}", queued_file, error)
-                };
+// Sends the log events to stderr, at the level chosen by -verbose or -vv unless RUST_LOG overrides it.
+fn init_logger(log_level:LevelFilter) {
+    env_logger::Builder::new().filter_level(log_level).parse_default_env().init();
+}
 
-                search_results.skipped_files.push(skipped_file);
-                continue;
-            }
-        };
+// Reads the paths of the files to search from stdin, one per line. Blank lines are ignored.
+fn read_stdin_paths() -> Result<Vec<String>, String> {
+    let mut input_files:Vec<String> = Vec::new();
 
-        let mut file_contents:Vec<u8> = Vec::new();
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => if !line.trim().is_empty() {
+                input_files.push(line);
+            },
 
-        let _ = match file_stream.read_to_end(&mut file_contents) {
-            Ok(bytes_read) => bytes_read,
-            Err(error) => {
-                let skipped_file = SkippedFile {
-                    file_path:queued_file.clone(),
-                    skip_reason:format!("Failed to read data from file @ {}, error: {:?}", queued_file, error)
-                };
+            Err(error) => return Err(format!("Couldn't read the file list from stdin, error: {}", error))
+        }
+    }
 
-                search_results.skipped_files.push(skipped_file);
-                continue;
-            }
+    Ok(input_files)
+}
+
+// How the search results are presented, parsed from the command line alongside the SearchOptions.
+struct DisplayOptions {
+    show_skipped:bool,
+    show_unmatched:bool,
+    show_locations:bool,
+    show_counts:bool,
+    count_lines:bool,
+    invert_match:bool,
+    show_stats:bool,
+    show_tally:bool,
+    quiet:bool,
+    summary_only:bool,
+    null_terminated:bool,
+    color_choice:ColorChoice,
+
+    // With -ctx, how many lines to print around each match. The encoding and -z are copied from the SearchOptions, so the lines are decoded like they were searched.
+    context_lines:Option<usize>,
+    show_carets:bool,
+    show_samples:bool,
+    group_by_pattern:bool,
+    group_by_directory:bool,
+    file_encoding:FileEncoding,
+    decompress_gzip:bool,
+
+    // With -relative, the search roots that are stripped from the printed paths.
+    relative_roots:Vec<String>,
+
+    // With -head, how many files to list at most, and once the results are truncated, how many files were left out of them.
+    head_limit:Option<usize>,
+    omitted_files:usize,
+
+    // With -format-width, how wide the matched patterns column is at most, longer pattern lists are cut short with an ellipsis.
+    format_width:Option<usize>,
+
+    // With -pattern-id, every pattern in the order of search_patterns, so that a pattern is printed as #<its position> instead.
+    pattern_ids:Option<Vec<String>>
+}
+
+// With -relative, the path below whichever search root it was found in, the deepest one if the roots are nested. A path outside of every root is printed as is.
+fn display_path<'a>(file_path:&'a str, display_options:&DisplayOptions) -> &'a str {
+    display_options.relative_roots.iter()
+        .filter_map(|relative_root| Path::new(file_path).strip_prefix(relative_root).ok().map(|relative_path| (Path::new(relative_root).components().count(), relative_path)))
+        .max_by_key(|(root_depth, _)| *root_depth)
+        .and_then(|(_, relative_path)| relative_path.to_str())
+        .filter(|relative_path| !relative_path.is_empty())
+        .unwrap_or(file_path)
+}
+
+// The JSON report is written straight from the search results, which are never read again after it, so their paths are stripped in place.
+fn strip_relative_roots(search_results:&mut SearchResults, display_options:&DisplayOptions) {
+    for matched_file in &mut search_results.matched_files {
+        matched_file.file_path = String::from(display_path(&matched_file.file_path, display_options));
+    }
+
+    for skipped_file in &mut search_results.skipped_files {
+        skipped_file.file_path = String::from(display_path(&skipped_file.file_path, display_options));
+    }
+
+    for unmatched_file in &mut search_results.unmatched_files {
+        *unmatched_file = String::from(display_path(unmatched_file, display_options));
+    }
+}
+
+// The order matched files are printed in, with -sort. Ties keep the default path order.
+#[derive(Clone, Copy, PartialEq)]
+enum SortMode {
+    Path,
+    Size,
+    Matches,
+    Mtime,
+    Score
+}
+
+fn sort_matched_files(matched_files:&mut [MatchedFile], sort_mode:SortMode, reverse:bool) {
+    matched_files.sort_by(|previous, current| {
+        let ordering:Ordering = match sort_mode {
+            SortMode::Path => previous.file_path.cmp(&current.file_path),
+            SortMode::Size => previous.file_size.cmp(&current.file_size),
+            SortMode::Matches => previous.match_count.cmp(&current.match_count),
+            SortMode::Mtime => previous.modified_seconds.cmp(&current.modified_seconds),
+            // The most relevant files come first, unlike the other modes.
+            SortMode::Score => current.score.cmp(&previous.score)
         };
 
-        let aho_corasick_search_alg:AhoCorasick = AhoCorasick::new(patterns);
+        if reverse { ordering.reverse() } else { ordering }
+    });
+}
 
-        let mut matched_patterns:Vec<String> = Vec::new();
+// With -head, drops every file past the first head_limit ones, which happens after sorting, so that they're the first ones in the sorted order.
+// With -v, the unmatched files are what's listed, so they're truncated instead.
+fn truncate_results(search_results:&mut SearchResults, display_options:&mut DisplayOptions) {
+    let head_limit:usize = match display_options.head_limit {
+        Some(head_limit) => head_limit,
+        None => return
+    };
 
-        for matched_pattern in aho_corasick_search_alg.find_iter(&file_contents) {
-            let pattern_as_string:&String = &patterns[matched_pattern.pattern()];
+    let listed_count:usize = if display_options.invert_match { search_results.unmatched_files.len() } else { search_results.matched_files.len() };
+    display_options.omitted_files = listed_count.saturating_sub(head_limit);
 
-            if !matched_patterns.contains(pattern_as_string) {
-                matched_patterns.push(pattern_as_string.clone());
-            }
+    if display_options.invert_match {
+        search_results.unmatched_files.truncate(head_limit);
+    } else {
+        search_results.matched_files.truncate(head_limit);
+    }
+}
+
+// Writes the provided text in the provided color, the color is dropped if the stream doesn't support it.
+fn write_colored(output_stream:&mut dyn WriteColor, text:&str, color:Color) -> io::Result<()> {
+    output_stream.set_color(ColorSpec::new().set_fg(Some(color)))?;
+    write!(output_stream, "{}", text)?;
+    output_stream.reset()
+}
+
+// The parts of a line covered by the provided matches, as merged byte ranges relative to the start of the line. The ranges are widened to
+// character boundaries, so that they never split a multi-byte character.
+fn highlight_spans(line:&str, line_start:usize, match_locations:&[MatchLocation]) -> Vec<(usize, usize)> {
+    let line_end:usize = line_start + line.len();
+    let mut spans:Vec<(usize, usize)> = Vec::new();
+
+    for match_location in match_locations {
+        let match_end:usize = match_location.byte_offset + match_location.match_length;
+
+        // Matches can span several lines, each of them only highlights its own part.
+        if match_location.byte_offset >= line_end || match_end <= line_start {
+            continue;
         }
 
-        if matched_patterns.len() > 0 {
-            let matched_file = MatchedFile {
-                file_path:queued_file.clone(),
-                matched_patterns:matched_patterns.clone()
-            };
+        let mut span_start:usize = match_location.byte_offset.max(line_start) - line_start;
+        let mut span_end:usize = match_end.min(line_end) - line_start;
 
-            search_results.matched_files.push(matched_file);
-        } else {
-            search_results.unmatched_files.push(queued_file.clone());
+        while !line.is_char_boundary(span_start) {
+            span_start -= 1;
+        }
+
+        while !line.is_char_boundary(span_end) {
+            span_end += 1;
+        }
+
+        if span_start < span_end {
+            spans.push((span_start, span_end));
         }
     }
 
-    println!();
-    
-    return Ok(search_results);
+    spans.sort();
+
+    let mut merged_spans:Vec<(usize, usize)> = Vec::new();
+
+    for (span_start, span_end) in spans {
+        match merged_spans.last_mut() {
+            Some(last_span) if span_start <= last_span.1 => last_span.1 = last_span.1.max(span_end),
+            _ => merged_spans.push((span_start, span_end))
+        }
+    }
+
+    merged_spans
 }
 
-const HELP_MESSAGE:&str = "
--spt    | [Necessary] The pattern(s) used to match files. Every argument past this one is considered a pattern, and thus it must be placed after other arguments.
--dir    | Specifies the directory to perform the operation, if not specified blank, assumes working directory.
--mfs    | Do not queue files that exceed this size in bytes.
--mfq    | Maximum amount of queued files allowed.
--ssk    | Show files that were skipped, and the reason behind skipping them.
--sum    | Show unmatched files (files that met the queue conditions, but didn't match any given pattern).
--ext    | Only queue files with one of the provided extensions, e.g. .cpp:.hpp
--h      | Displays this help message.
-";
+// Writes a context line with its matches highlighted. Without color, the matches can instead be marked with carets on the line below,
+// like compiler diagnostics, which are indented by prefix_width to line up with the line after its path:line: prefix.
+fn write_highlighted_line(output_stream:&mut dyn WriteColor, line:&str, spans:&[(usize, usize)], prefix_width:usize, show_carets:bool) -> io::Result<()> {
+    if output_stream.supports_color() {
+        let mut written_up_to:usize = 0;
+
+        for &(span_start, span_end) in spans {
+            write!(output_stream, "{}", &line[written_up_to..span_start])?;
+            output_stream.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            write!(output_stream, "{}", &line[span_start..span_end])?;
+            output_stream.reset()?;
+            written_up_to = span_end;
+        }
 
-fn main() {
-    let mut target_directory:String         =       String::from(".");
+        return writeln!(output_stream, "{}", &line[written_up_to..]);
+    }
 
-    let mut file_extensions:Vec<String>     =       Vec::new();
-    let mut search_patterns:Vec<String>     =       Vec::new();
+    writeln!(output_stream, "{}", line)?;
+
+    let last_span_end:usize = match spans.last() {
+        Some(&(_, span_end)) if show_carets => span_end,
+        _ => return Ok(())
+    };
+
+    // Tabs are kept as they are, so that the carets stay aligned however wide the terminal renders them.
+    let caret_line:String = line[..last_span_end].char_indices().map(|(char_offset, line_char)| {
+        if spans.iter().any(|&(span_start, span_end)| char_offset >= span_start && char_offset < span_end) {
+            '^'
+        } else if line_char == '\t' {
+            '\t'
+        } else {
+            ' '
+        }
+    }).collect();
 
-    let mut maximum_file_size:u64           =       0;
-    let mut maximum_files_queued:usize      =       0;
+    writeln!(output_stream, "{}{}", " ".repeat(prefix_width), caret_line)
+}
 
-    let mut show_unmatched:bool             =       false;
-    let mut show_skipped:bool               =       false;
+// Prints the lines around every match, grep -C style. Overlapping ranges are merged, and every range is separated by --.
+fn print_context(output_stream:&mut dyn WriteColor, search_results:&SearchResults, context_lines:usize, display_options:&DisplayOptions) -> io::Result<()> {
+    let mut printed_any:bool = false;
 
-    // Create a peekable iterator over the console arguments.
-    let mut argument_iterator = env::args().peekable();
+    for matched_file in &search_results.matched_files {
+        let mut line_numbers:Vec<usize> = matched_file.match_locations.iter().filter_map(|match_location| match_location.line_number).collect();
 
-    // Parse arguments in argument iterator.
-    loop {
-        let argument = match argument_iterator.next() {
-            Some(argument) => argument,
-            None => break
-        };
+        // Binary files have no line numbers, and their lines wouldn't be readable anyway.
+        if line_numbers.is_empty() {
+            writeln!(output_stream, "Binary file {} matches, context not shown.", display_path(&matched_file.file_path, display_options))?;
+            continue;
+        }
 
-        let peek_result = argument_iterator.peek();
+        line_numbers.sort();
+        line_numbers.dedup();
 
-        let next_argument_present:bool = match peek_result {
-            Some(_) => true,
-            None => false
+        let file_text:String = match read_file_text(&matched_file.file_path, display_options.file_encoding, display_options.decompress_gzip) {
+            Ok(file_text) => file_text,
+            Err(error) => {
+                writeln!(output_stream, "Couldn't read the context of {}, error: {}", display_path(&matched_file.file_path, display_options), error)?;
+                continue;
+            }
         };
 
-        let next_argument:&String = match peek_result {
-            Some(string) => string,
-            None => &argument
-        };
+        // Every line along with the byte offset it starts at, without its line ending, so that matches can be highlighted within it.
+        let mut file_lines:Vec<(usize, &str)> = Vec::new();
+        let mut line_start:usize = 0;
 
-        match &argument as &str {
-            "-h" => {
-                println!("{}", HELP_MESSAGE);
-                return;
-            },
+        for line in file_text.split_inclusive('\n') {
+            let line_content:&str = line.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(line);
+            file_lines.push((line_start, line_content));
+            line_start += line.len();
+        }
 
-            "-ssk" => {
-                show_skipped = true;
-            }
-            
-            "-sum" => {
-                show_unmatched = true;
-            }
+        // Every range is an inclusive pair of 1-based line numbers.
+        let mut context_ranges:Vec<(usize, usize)> = Vec::new();
 
-            "-mfs" => if next_argument_present {
-                maximum_file_size = match next_argument.parse() {
-                    Ok(value) => value,
-                    Err(error) => {
-                        panic!("Could not convert the provided maximum file size into an integer, error: {:?}", error);
-                    }
-                };
-            }
-            
-            "-mfq" => if next_argument_present {
-                maximum_files_queued = match next_argument.parse() {
-                    Ok(value) => value,
-                    Err(error) => {
-                        panic!("Could not convert the provided maximum queued file count into an integer, error: {:?}", error);
-                    }
-                };
-            }
+        for line_number in &line_numbers {
+            let range_start:usize = line_number.saturating_sub(context_lines).max(1);
+            let range_end:usize = (line_number + context_lines).min(file_lines.len());
 
-            "-dir" => if next_argument_present {
-                target_directory = next_argument.clone();
+            match context_ranges.last_mut() {
+                Some(last_range) if range_start <= last_range.1 + 1 => last_range.1 = range_end.max(last_range.1),
+                _ => context_ranges.push((range_start, range_end))
             }
+        }
 
-            "-ext" => if next_argument_present {
-                for extension in next_argument.split(":") {
-                    file_extensions.push(String::from(extension));
-                }
+        for (range_start, range_end) in context_ranges {
+            if printed_any {
+                writeln!(output_stream, "--")?;
             }
 
-            "-spt" => if next_argument_present {
-                loop {
-                    match argument_iterator.next() {
-                        Some(pattern) => search_patterns.push(pattern),
-                        None => break
-                    };
-                }
-            }
+            printed_any = true;
 
-            _ => {
-                continue;
+            for line_number in range_start..=range_end {
+                let separator:char = if line_numbers.binary_search(&line_number).is_ok() { ':' } else { '-' };
+
+                let (line_start, line) = file_lines[line_number - 1];
+                let spans:Vec<(usize, usize)> = highlight_spans(line, line_start, &matched_file.match_locations);
+                let line_prefix:String = format!("{}{}{}", separator, line_number, separator);
+
+                let file_path:&str = display_path(&matched_file.file_path, display_options);
+
+                write_colored(output_stream, file_path, Color::Green)?;
+                write!(output_stream, "{}", line_prefix)?;
+                write_highlighted_line(output_stream, line, &spans, file_path.chars().count() + line_prefix.len(), display_options.show_carets)?;
             }
-        };
+        }
     }
 
-    if search_patterns.len() > 0 {
-        println!("Performing content search with the following parameters.");
-        println!("\n{}", "-".repeat(50));
-        println!("Search Patterns: {:?}", search_patterns);
-        println!("Target Dir: {}", target_directory);
-        println!("File Extensions: {:?}", file_extensions);
-        println!("Max File Size: {}", maximum_file_size);
-        println!("Max Queued Files: {}", maximum_files_queued);
-        println!("{}", "-".repeat(50));
+    Ok(())
+}
 
-        let search_results:SearchResults = match perform_search(&target_directory, &file_extensions, &search_patterns, &maximum_file_size, &maximum_files_queued) {
-            Ok(search_results) => search_results,
-            Err(error) => {
-                println!("perform_search Returned an error: {:?}", error);
-                return;
-            }
-        };
+// How a pattern is printed in the per-file output, #<index> with -pattern-id.
+fn pattern_label(pattern:&str, display_options:&DisplayOptions) -> String {
+    match display_options.pattern_ids.as_ref().and_then(|pattern_ids| pattern_ids.iter().position(|pattern_id| pattern_id == pattern)) {
+        Some(pattern_index) => format!("#{}", pattern_index),
+        None => format!("{:?}", pattern)
+    }
+}
+
+// The matched patterns column of a matched file, e.g. ["foo", "bar"], or [#0, #1] with -pattern-id.
+fn matched_patterns_label(matched_file:&MatchedFile, display_options:&DisplayOptions) -> String {
+    if display_options.pattern_ids.is_none() {
+        return format!("{:?}", matched_file.matched_patterns);
+    }
+
+    let pattern_labels:Vec<String> = matched_file.matched_patterns.iter().map(|matched_pattern| pattern_label(matched_pattern, display_options)).collect();
+    format!("[{}]", pattern_labels.join(", "))
+}
+
+// With -pattern-id, which pattern every #<index> stands for, printed once ahead of the files.
+fn print_pattern_legend(output_stream:&mut dyn WriteColor, display_options:&DisplayOptions) -> io::Result<()> {
+    let pattern_ids:&Vec<String> = match &display_options.pattern_ids {
+        Some(pattern_ids) => pattern_ids,
+        None => return Ok(())
+    };
+
+    for (pattern_index, pattern) in pattern_ids.iter().enumerate() {
+        write_colored(output_stream, &format!("#{}", pattern_index), Color::Yellow)?;
+        writeln!(output_stream, " = {:?}", pattern)?;
+    }
+
+    writeln!(output_stream, "{}", "-".repeat(50))
+}
+
+// With -by-dir, every directory that holds matched files directly, along with how many matched and how many matches they hold in total.
+// The directories with the most matches come first, ties are sorted by path.
+fn print_directory_groups(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    let mut directory_groups:HashMap<String, (usize, usize)> = HashMap::new();
 
-        let matched_patterns_padsize:usize = match search_results.matched_files.iter().map(|matched_file| format!("{:?}", matched_file.matched_patterns)).max_by(|previous, current| previous.len().cmp(&current.len())) {
-            Some(largest_string) => largest_string.len(),
-            None => 0,
+    for matched_file in &search_results.matched_files {
+        let directory:String = match Path::new(&matched_file.file_path).parent() {
+            Some(directory) => directory.to_string_lossy().into_owned(),
+            None => String::new()
         };
-    
-        println!("{}", "-".repeat(50));
-        
-        if show_skipped {
-            for skipped_file in &search_results.skipped_files {
-                println!("SKIPPED({}) - {}", skipped_file.skip_reason, skipped_file.file_path);
-            }
-            
-            println!("{}", "-".repeat(50));
+
+        let (file_count, match_count) = directory_groups.entry(directory).or_default();
+        *file_count += 1;
+        *match_count += matched_file.match_count;
+    }
+
+    let mut directory_groups:Vec<(String, (usize, usize))> = directory_groups.into_iter().collect();
+    directory_groups.sort_by(|previous, current| current.1.1.cmp(&previous.1.1).then_with(|| previous.0.cmp(&current.0)));
+
+    for (directory, (file_count, match_count)) in directory_groups {
+        write!(output_stream, "{} MATCHES IN {} FILES | ", match_count, file_count)?;
+        write_colored(output_stream, display_path(&directory, display_options), Color::Green)?;
+        writeln!(output_stream)?;
+    }
+
+    Ok(())
+}
+
+// With -group-by-pattern, every pattern that matched followed by the files it matched in, rather than every file followed by its patterns.
+// The patterns are in alphabetical order, and the files within each group are sorted by path.
+fn print_pattern_groups(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    let mut pattern_groups:HashMap<String, Vec<String>> = HashMap::new();
+
+    for matched_file in &search_results.matched_files {
+        for matched_pattern in &matched_file.matched_patterns {
+            pattern_groups.entry(matched_pattern.clone()).or_default().push(matched_file.file_path.clone());
         }
-        
-        if show_unmatched {
-            for unmatched_file in &search_results.unmatched_files {
-                println!("DIDN'T MATCH - {}", unmatched_file);
-            }
+    }
 
-            println!("{}", "-".repeat(50));
+    let mut pattern_groups:Vec<(String, Vec<String>)> = pattern_groups.into_iter().collect();
+    pattern_groups.sort_by(|previous, current| previous.0.cmp(&current.0));
+
+    for (pattern, mut file_paths) in pattern_groups {
+        file_paths.sort();
+
+        write_colored(output_stream, &pattern_label(&pattern, display_options), Color::Yellow)?;
+        writeln!(output_stream, " | MATCHED IN {} FILES", file_paths.len())?;
+
+        for file_path in &file_paths {
+            write!(output_stream, "    ")?;
+            write_colored(output_stream, display_path(file_path, display_options), Color::Green)?;
+            writeln!(output_stream)?;
         }
+    }
+
+    Ok(())
+}
+
+// The count printed with -count, the total amount of matches, or with -count-lines how many distinct lines hold one, or with -null-data records.
+// Matches within the path are on no line, so they don't count towards the latter.
+fn displayed_count(matched_file:&MatchedFile, display_options:&DisplayOptions) -> usize {
+    if !display_options.count_lines {
+        return matched_file.match_count;
+    }
+
+    if let Some(matched_records) = &matched_file.matched_records {
+        return matched_records.len();
+    }
+
+    let matched_lines:HashSet<usize> = matched_file.match_locations.iter().filter_map(|match_location| match_location.line_number).collect();
+    matched_lines.len()
+}
+
+// Prints the line of a single matched file, with its patterns padded to the provided width so that the paths of several files line up.
+fn print_matched_file(output_stream:&mut dyn WriteColor, matched_file:&MatchedFile, matched_patterns_padsize:usize, display_options:&DisplayOptions) -> io::Result<()> {
+    if display_options.show_counts {
+        write!(output_stream, "{}\t", displayed_count(matched_file, display_options))?;
+        write_colored(output_stream, display_path(&matched_file.file_path, display_options), Color::Green)?;
+        return writeln!(output_stream);
+    }
+
+    if let Some(score) = matched_file.score {
+        write!(output_stream, "{}\t", score)?;
+    }
+
+    let mut matched_patterns_str:String = matched_patterns_label(matched_file, display_options);
+    let mut matched_patterns_padsize:usize = matched_patterns_padsize;
+
+    // Widths are counted in characters here, so that the ellipsis and multi-byte patterns don't throw off the alignment.
+    if let Some(format_width) = display_options.format_width {
+        matched_patterns_padsize = matched_patterns_padsize.min(format_width);
+
+        if matched_patterns_str.chars().count() > format_width {
+            matched_patterns_str = matched_patterns_str.chars().take(format_width - 1).chain(std::iter::once('…')).collect();
+        }
+    }
+
+    write_colored(output_stream, &matched_patterns_str, Color::Yellow)?;
+
+    let matched_patterns_width:usize = if display_options.format_width.is_some() { matched_patterns_str.chars().count() } else { matched_patterns_str.len() };
+
+    if matched_patterns_width < matched_patterns_padsize {
+        write!(output_stream, "{}", " ".repeat(matched_patterns_padsize - matched_patterns_width))?;
+    }
+
+    write!(output_stream, " | MATCHED IN > ")?;
+    write_colored(output_stream, display_path(&matched_file.file_path, display_options), Color::Green)?;
+
+    if matched_file.match_cap_reached {
+        write!(output_stream, " (stopped after {} matches)", matched_file.match_count)?;
+    }
+
+    if let Some(matched_records) = &matched_file.matched_records {
+        let record_indices:Vec<String> = matched_records.iter().map(|record_index| record_index.to_string()).collect();
+        write!(output_stream, " (records {})", record_indices.join(", "))?;
+    }
+
+    if let Some(first_match_sample) = matched_file.first_match_sample.as_ref().filter(|_| display_options.show_samples) {
+        write!(output_stream, " | {}", first_match_sample)?;
+    }
+
+    writeln!(output_stream)
+}
+
+// Prints the human-readable listing of the search results.
+fn print_results(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    let matched_patterns_padsize:usize = match search_results.matched_files.iter().map(|matched_file| matched_patterns_label(matched_file, display_options)).max_by(|previous, current| previous.len().cmp(&current.len())) {
+        Some(largest_string) => largest_string.len(),
+        None => 0,
+    };
+
+    writeln!(output_stream, "{}", "-".repeat(50))?;
+
+    if !display_options.invert_match {
+        print_pattern_legend(output_stream, display_options)?;
+    }
     
-        for matched_file in &search_results.matched_files {
-            let mut matched_patterns_str:String = format!("{:?}", matched_file.matched_patterns);
-    
-            if matched_patterns_str.len() < matched_patterns_padsize {
-                matched_patterns_str += " ".repeat(matched_patterns_padsize - matched_patterns_str.len()).as_str();
-            }
-    
-            println!("{} | MATCHED IN > {}", matched_patterns_str, matched_file.file_path);
+    if display_options.show_skipped {
+        for skipped_file in &search_results.skipped_files {
+            write!(output_stream, "SKIPPED({}) - ", skipped_file.skip_reason)?;
+            write_colored(output_stream, display_path(&skipped_file.file_path, display_options), Color::Red)?;
+            writeln!(output_stream)?;
+        }
+
+        // These were still searched, just not the way that was asked for.
+        for search_fallback in &search_results.search_fallbacks {
+            write!(output_stream, "FALLBACK({}) - ", search_fallback.skip_reason)?;
+            write_colored(output_stream, display_path(&search_fallback.file_path, display_options), Color::Yellow)?;
+            writeln!(output_stream)?;
         }
+        
+        writeln!(output_stream, "{}", "-".repeat(50))?;
+    }
     
-        println!("{}", "-".repeat(50));
+    if display_options.show_unmatched {
+        for unmatched_file in &search_results.unmatched_files {
+            write!(output_stream, "DIDN'T MATCH - ")?;
+            write_colored(output_stream, display_path(unmatched_file, display_options), Color::Cyan)?;
+            writeln!(output_stream)?;
+        }
 
-        println!("Matched {} files, {} unmatched candidates, {} files skipped.", search_results.matched_files.len(), search_results.unmatched_files.len(), search_results.skipped_files.len());
-    } else {
-        println!("Please specify at least one search pattern.");
+        writeln!(output_stream, "{}", "-".repeat(50))?;
     }
-}
\ No newline at end of file
+
+    if display_options.invert_match {
+        for unmatched_file in &search_results.unmatched_files {
+            if display_options.show_counts {
+                write!(output_stream, "0\t")?;
+            } else {
+                write!(output_stream, "NO MATCH IN > ")?;
+            }
+
+            write_colored(output_stream, display_path(unmatched_file, display_options), Color::Cyan)?;
+            writeln!(output_stream)?;
+        }
+
+        writeln!(output_stream, "{}", "-".repeat(50))?;
+    }
+
+    if display_options.group_by_pattern && !display_options.invert_match {
+        print_pattern_groups(output_stream, search_results, display_options)?;
+    }
+
+    if display_options.group_by_directory && !display_options.invert_match {
+        print_directory_groups(output_stream, search_results, display_options)?;
+    }
+
+    for matched_file in search_results.matched_files.iter().filter(|_| !display_options.invert_match && !display_options.group_by_pattern && !display_options.group_by_directory) {
+        print_matched_file(output_stream, matched_file, matched_patterns_padsize, display_options)?;
+    }
+
+    if !display_options.invert_match {
+        writeln!(output_stream, "{}", "-".repeat(50))?;
+    }
+
+    if display_options.show_locations && !display_options.invert_match {
+        for matched_file in &search_results.matched_files {
+            for match_location in &matched_file.match_locations {
+                let line_number:String = match match_location.line_number {
+                    Some(line_number) => line_number.to_string(),
+                    None => String::from("-")
+                };
+
+                write_colored(output_stream, display_path(&matched_file.file_path, display_options), Color::Green)?;
+                write!(output_stream, ":{}:{}: ", line_number, match_location.byte_offset)?;
+                let pattern:String = if display_options.pattern_ids.is_some() { pattern_label(&match_location.pattern, display_options) } else { match_location.pattern.clone() };
+                write_colored(output_stream, &pattern, Color::Yellow)?;
+                writeln!(output_stream)?;
+            }
+        }
+
+        writeln!(output_stream, "{}", "-".repeat(50))?;
+    }
+
+    if let Some(context_lines) = display_options.context_lines.filter(|_| !display_options.invert_match) {
+        print_context(output_stream, search_results, context_lines, display_options)?;
+        writeln!(output_stream, "{}", "-".repeat(50))?;
+    }
+
+    print_summary(output_stream, search_results, display_options)
+}
+
+// Prints the final counts, followed by the -stats and -tally lines if they were requested.
+fn print_summary(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    writeln!(output_stream, "Matched {} files, {} unmatched candidates, {} files skipped.", search_results.matched_files.len(), search_results.unmatched_files.len(), search_results.skipped_files.len())?;
+
+    if display_options.omitted_files > 0 {
+        writeln!(output_stream, "Only the first {} files are included, {} more were left out by -head.", display_options.head_limit.unwrap_or(0), display_options.omitted_files)?;
+    }
+
+    if display_options.show_stats {
+        let megabytes_read:f64 = search_results.bytes_read as f64 / (1024.0 * 1024.0);
+        let throughput:f64 = if search_results.elapsed_seconds > 0.0 { megabytes_read / search_results.elapsed_seconds } else { 0.0 };
+
+        writeln!(output_stream, "Searched {:.2} MB in {:.3} seconds ({:.2} MB/s).", megabytes_read, search_results.elapsed_seconds, throughput)?;
+    }
+
+    // The busiest patterns come first, ties are broken alphabetically.
+    if display_options.show_tally {
+        let mut pattern_tally:Vec<(&String, &usize)> = search_results.pattern_tally.iter().collect();
+        pattern_tally.sort_by(|previous, current| current.1.cmp(previous.1).then(previous.0.cmp(current.0)));
+
+        for (pattern, match_count) in pattern_tally {
+            write_colored(output_stream, pattern, Color::Yellow)?;
+            writeln!(output_stream, ": {}", match_count)?;
+        }
+    }
+
+    Ok(())
+}
+
+// A piece of the -printf format, which is parsed once and rendered for every matched file.
+enum PrintfToken {
+    Literal(String),
+    Path,
+    MatchCount,
+    Patterns,
+    Size,
+    Newline
+}
+
+// Splits the -printf format into its literal text and placeholders, %% being a literal %.
+fn parse_printf_format(printf_format:&str) -> Result<Vec<PrintfToken>, String> {
+    let mut printf_tokens:Vec<PrintfToken> = Vec::new();
+    let mut literal_text:String = String::new();
+    let mut format_chars = printf_format.chars();
+
+    while let Some(format_char) = format_chars.next() {
+        if format_char != '%' {
+            literal_text.push(format_char);
+            continue;
+        }
+
+        let printf_token:PrintfToken = match format_chars.next() {
+            Some('%') => {
+                literal_text.push('%');
+                continue;
+            }
+
+            Some('p') => PrintfToken::Path,
+            Some('c') => PrintfToken::MatchCount,
+            Some('m') => PrintfToken::Patterns,
+            Some('s') => PrintfToken::Size,
+            Some('n') => PrintfToken::Newline,
+            Some(placeholder) => return Err(format!("Unknown placeholder (%{}) in the -printf format, expected %p, %c, %m, %s, %n or %%.", placeholder)),
+            None => return Err(String::from("The -printf format ends with a lone %, use %% for a literal one."))
+        };
+
+        if !literal_text.is_empty() {
+            printf_tokens.push(PrintfToken::Literal(literal_text.split_off(0)));
+        }
+
+        printf_tokens.push(printf_token);
+    }
+
+    if !literal_text.is_empty() {
+        printf_tokens.push(PrintfToken::Literal(literal_text));
+    }
+
+    Ok(printf_tokens)
+}
+
+// Prints every matched file in the -printf format, nothing else. Like find's -printf, nothing is added after each file unless the format has %n.
+fn print_printf_results(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions, printf_tokens:&[PrintfToken]) -> io::Result<()> {
+    for matched_file in &search_results.matched_files {
+        for printf_token in printf_tokens {
+            match printf_token {
+                PrintfToken::Literal(literal_text) => write!(output_stream, "{}", literal_text)?,
+                PrintfToken::Path => write!(output_stream, "{}", display_path(&matched_file.file_path, display_options))?,
+                PrintfToken::MatchCount => write!(output_stream, "{}", matched_file.match_count)?,
+                PrintfToken::Patterns => write!(output_stream, "{}", matched_file.matched_patterns.join(";"))?,
+                PrintfToken::Size => write!(output_stream, "{}", matched_file.file_size)?,
+                PrintfToken::Newline => writeln!(output_stream)?
+            };
+        }
+    }
+
+    Ok(())
+}
+
+// Prints nothing but the selected file paths, one per line, for piping into other tools. With -0, every path ends with a NUL byte instead.
+fn print_quiet_results(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    let path_terminator:&str = if display_options.null_terminated { "\0" } else { "\n" };
+
+    if display_options.invert_match {
+        for unmatched_file in &search_results.unmatched_files {
+            if display_options.show_counts {
+                write!(output_stream, "0\t")?;
+            }
+
+            write_colored(output_stream, display_path(unmatched_file, display_options), Color::Cyan)?;
+            write!(output_stream, "{}", path_terminator)?;
+        }
+
+        return Ok(());
+    }
+
+    for matched_file in &search_results.matched_files {
+        if display_options.show_counts {
+            write!(output_stream, "{}\t", displayed_count(matched_file, display_options))?;
+        }
+
+        write_colored(output_stream, display_path(&matched_file.file_path, display_options), Color::Green)?;
+        write!(output_stream, "{}", path_terminator)?;
+    }
+
+    Ok(())
+}
+
+// Prints one path,match_count,patterns row per selected file, the patterns are joined with a ;.
+fn print_csv_results(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions, include_header:bool) -> io::Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(output_stream);
+
+    if include_header {
+        csv_writer.write_record(["path", "match_count", "patterns"])?;
+    }
+
+    if display_options.invert_match {
+        for unmatched_file in &search_results.unmatched_files {
+            csv_writer.write_record([display_path(unmatched_file, display_options), "0", ""])?;
+        }
+    } else {
+        for matched_file in &search_results.matched_files {
+            csv_writer.write_record([display_path(&matched_file.file_path, display_options), &matched_file.match_count.to_string(), &matched_file.matched_patterns.join(";")])?;
+        }
+    }
+
+    csv_writer.flush()
+}
+
+// Prints the search results as a JSON object.
+fn print_json_results(output_stream:&mut dyn WriteColor, search_results:&SearchResults, display_options:&DisplayOptions) -> io::Result<()> {
+    let json_report = JsonReport {
+        schema_version:JSON_SCHEMA_VERSION,
+        inverted:display_options.invert_match,
+        matched_count:search_results.matched_files.len(),
+        skipped_count:search_results.skipped_files.len(),
+        unmatched_count:search_results.unmatched_files.len(),
+        matched_files:if display_options.invert_match { None } else { Some(&search_results.matched_files) },
+        skipped_files:&search_results.skipped_files,
+        unmatched_files:&search_results.unmatched_files
+    };
+
+    serde_json::to_writer_pretty(&mut *output_stream, &json_report)?;
+    writeln!(output_stream)
+}
+
+// Exit codes, following grep's convention.
+const EXIT_MATCHED:i32 = 0;
+const EXIT_UNMATCHED:i32 = 1;
+const EXIT_ERROR:i32 = 2;
+
+// The exit code for a finished search, depending on whether any file was selected. With -v, the selected files are the ones that didn't match.
+fn exit_code_for(search_results:&SearchResults, display_options:&DisplayOptions) -> i32 {
+    let selected_any:bool = if display_options.invert_match {
+        !search_results.unmatched_files.is_empty()
+    } else {
+        !search_results.matched_files.is_empty()
+    };
+
+    if selected_any { EXIT_MATCHED } else { EXIT_UNMATCHED }
+}
+
+// Benchmark runs always use the same corpus, so that their timings can be compared across configurations.
+const BENCHMARK_SEED:u64 = 0x5EED;
+
+// Writes a synthetic corpus to a temporary directory and searches it for the planted patterns with the provided options, reporting how long
+// each step took. Only the directories and patterns are replaced, so the flags being compared, e.g. -threads or -re, still apply.
+fn run_benchmark(mut search_options:SearchOptions, corpus_size:u64, pattern_count:usize) -> Result<(), String> {
+    let generation_start:Instant = Instant::now();
+    let synthetic_corpus:SyntheticCorpus = generate_corpus(BENCHMARK_SEED, corpus_size, pattern_count);
+
+    let corpus_directory:PathBuf = env::temp_dir().join(format!("content-search-benchmark-{}", process::id()));
+
+    if let Err(error) = fs::create_dir_all(&corpus_directory) {
+        return Err(format!("Couldn't create the benchmark directory @ {}, error: {}", corpus_directory.display(), error));
+    }
+
+    for (file_index, file_contents) in synthetic_corpus.files.iter().enumerate() {
+        let file_path:PathBuf = corpus_directory.join(format!("corpus{:05}.txt", file_index));
+
+        if let Err(error) = fs::write(&file_path, file_contents) {
+            let _ = fs::remove_dir_all(&corpus_directory);
+            return Err(format!("Couldn't write the benchmark file @ {}, error: {}", file_path.display(), error));
+        }
+    }
+
+    println!("Generated {} files ({} bytes) with {} patterns in {:.3} seconds.", synthetic_corpus.files.len(), corpus_size, synthetic_corpus.patterns.len(), generation_start.elapsed().as_secs_f64());
+
+    search_options.directories = vec![corpus_directory.to_string_lossy().into_owned()];
+    search_options.patterns = synthetic_corpus.patterns;
+    search_options.literal_patterns = Vec::new();
+    search_options.hex_patterns = Vec::new();
+    search_options.input_files = None;
+    search_options.changed_since = None;
+
+    let search_result:Result<SearchResults, String> = perform_search(&search_options);
+    let _ = fs::remove_dir_all(&corpus_directory);
+
+    let search_results:SearchResults = search_result?;
+    let megabytes_read:f64 = search_results.bytes_read as f64 / (1024.0 * 1024.0);
+    let throughput:f64 = if search_results.elapsed_seconds > 0.0 { megabytes_read / search_results.elapsed_seconds } else { 0.0 };
+    let match_count:usize = search_results.matched_files.iter().map(|matched_file| matched_file.match_count).sum();
+
+    println!("Searched {:.2} MB in {:.3} seconds ({:.2} MB/s).", megabytes_read, search_results.elapsed_seconds, throughput);
+    println!("Matched {} files with {} matches, {} files skipped.", search_results.matched_files.len(), match_count, search_results.skipped_files.len());
+
+    Ok(())
+}
+
+// How long the watcher waits for the events to settle before searching the changed files, as saving a file often takes several writes.
+const WATCH_DEBOUNCE:Duration = Duration::from_millis(300);
+
+// A file that's written to nonstop would never settle, so the changes are searched after this long regardless.
+const WATCH_MAX_DELAY:Duration = Duration::from_secs(2);
+
+// Watches the target directories after the initial search, and searches the files that are created or modified within them, until the program is stopped.
+// The changed files are found by walking the directories again, so that every filter applies to them like it did to the initial search. Only newly
+// matched files are printed, the files in ignored_paths, i.e. those the program writes itself, never trigger a search.
+fn run_watch(mut search_options:SearchOptions, output_stream:&mut dyn WriteColor, display_options:&DisplayOptions, printf_tokens:Option<&[PrintfToken]>, ignored_paths:&[String]) -> Result<(), String> {
+    let (event_sender, event_receiver) = mpsc::channel::<notify::Result<notify::Event>>();
+
+    let mut directory_watcher:RecommendedWatcher = match notify::recommended_watcher(event_sender) {
+        Ok(directory_watcher) => directory_watcher,
+        Err(error) => return Err(format!("Couldn't create the file watcher, error: {}", error))
+    };
+
+    let recursive_mode:RecursiveMode = if search_options.max_depth == Some(0) { RecursiveMode::NonRecursive } else { RecursiveMode::Recursive };
+
+    for directory in &search_options.directories {
+        if let Err(error) = directory_watcher.watch(Path::new(directory), recursive_mode) {
+            return Err(format!("Couldn't watch the directory ({}), error: {}", directory, error));
+        }
+    }
+
+    let ignored_paths:HashSet<PathBuf> = ignored_paths.iter().map(|ignored_path| fs::canonicalize(ignored_path).unwrap_or_else(|_| PathBuf::from(ignored_path))).collect();
+
+    // The progress bars would be redrawn for every handful of changed files.
+    search_options.show_progress = false;
+
+    eprintln!("Watching {:?} for changes, press Ctrl+C to stop.", search_options.directories);
+
+    // The receiver only disconnects once the watcher is dropped, which doesn't happen before returning.
+    while let Ok(first_event) = event_receiver.recv() {
+        let batch_start:Instant = Instant::now();
+        let mut changed_files:HashSet<PathBuf> = HashSet::new();
+        let mut next_event:Option<notify::Result<notify::Event>> = Some(first_event);
+
+        while let Some(watch_event) = next_event.take() {
+            match watch_event {
+                Ok(watch_event) => if matches!(watch_event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    // Files that were removed again before the events settled can't be canonicalized, and there's nothing left to search in them.
+                    changed_files.extend(watch_event.paths.iter().filter_map(|event_path| fs::canonicalize(event_path).ok()));
+                },
+
+                Err(error) => warn!("The file watcher reported an error: {}", error)
+            }
+
+            if batch_start.elapsed() < WATCH_MAX_DELAY {
+                next_event = event_receiver.recv_timeout(WATCH_DEBOUNCE).ok();
+            }
+        }
+
+        changed_files.retain(|changed_file| changed_file.is_file() && !ignored_paths.contains(changed_file));
+
+        if changed_files.is_empty() {
+            continue;
+        }
+
+        debug!("Searching {} changed files", changed_files.len());
+        search_options.only_files = Some(changed_files);
+
+        let search_results:SearchResults = perform_search(&search_options)?;
+
+        let print_result:io::Result<()> = if let Some(printf_tokens) = printf_tokens {
+            print_printf_results(output_stream, &search_results, display_options, printf_tokens)
+        } else if display_options.quiet {
+            print_quiet_results(output_stream, &search_results, display_options)
+        } else {
+            let matched_patterns_padsize:usize = search_results.matched_files.iter().map(|matched_file| matched_patterns_label(matched_file, display_options).len()).max().unwrap_or(0);
+
+            search_results.matched_files.iter().try_for_each(|matched_file| print_matched_file(output_stream, matched_file, matched_patterns_padsize, display_options))
+        };
+
+        if let Err(error) = print_result.and_then(|_| output_stream.flush()) {
+            return Err(format!("Couldn't write the search results, error: {:?}", error));
+        }
+    }
+
+    Ok(())
+}
+
+// Prints the lines that a replacement changes, the original ones first, each prefixed with its line number.
+fn print_replaced_lines(output_stream:&mut dyn WriteColor, file_replacement:&FileReplacement) -> io::Result<()> {
+    for replaced_lines in &file_replacement.replaced_lines {
+        for (line_offset, original_line) in replaced_lines.original_lines.split('\n').enumerate() {
+            write_colored(output_stream, &format!("  -{}: {}", replaced_lines.line_number + line_offset, original_line), Color::Red)?;
+            writeln!(output_stream)?;
+        }
+
+        for (line_offset, replaced_line) in replaced_lines.replaced_lines.split('\n').enumerate() {
+            write_colored(output_stream, &format!("  +{}: {}", replaced_lines.line_number + line_offset, replaced_line), Color::Green)?;
+            writeln!(output_stream)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Replaces the matches within every matched file and writes the files back, after copying each one to <path>.bak with backup. With dry_run,
+// nothing is written, and the lines that would change are printed instead. Files that were refused, e.g. binary ones, are listed but aren't
+// an error, whereas failing to write one is, once every other file was written.
+#[allow(clippy::too_many_arguments)]
+fn run_replacements(output_stream:&mut dyn WriteColor, search_results:&SearchResults, search_options:&SearchOptions, display_options:&DisplayOptions, replacement:&str, backup:bool, allow_binary:bool, dry_run:bool) -> Result<(), String> {
+    let (file_replacements, mut refused_files):(Vec<FileReplacement>, Vec<SkippedFile>) = plan_replacements(&search_results.matched_files, search_options, replacement, allow_binary)?;
+    let write_error = |error:io::Error| format!("Couldn't write the replacements, error: {:?}", error);
+
+    let mut replaced_files:usize = 0;
+    let mut replaced_matches:usize = 0;
+    let mut failed_writes:usize = 0;
+
+    if !display_options.quiet {
+        writeln!(output_stream, "{}", "-".repeat(50)).map_err(write_error)?;
+    }
+
+    for file_replacement in &file_replacements {
+        if !dry_run {
+            let backup_path:String = format!("{}.bak", file_replacement.file_path);
+
+            let written:io::Result<()> = if backup { fs::copy(&file_replacement.file_path, &backup_path).map(|_| ()) } else { Ok(()) }
+                .and_then(|_| fs::write(&file_replacement.file_path, &file_replacement.replaced_contents));
+
+            if let Err(error) = written {
+                refused_files.push(SkippedFile {
+                    file_path:file_replacement.file_path.clone(),
+                    skip_reason:SkipReason::IoError(format!("Couldn't write the file, error: {}", error)),
+                    skip_phase:SkipPhase::Replacing
+                });
+                failed_writes += 1;
+                continue;
+            }
+        }
+
+        replaced_files += 1;
+        replaced_matches += file_replacement.replacement_count;
+
+        if display_options.quiet {
+            let path_terminator:&str = if display_options.null_terminated { "\0" } else { "\n" };
+            write_colored(output_stream, display_path(&file_replacement.file_path, display_options), Color::Green).map_err(write_error)?;
+            write!(output_stream, "{}", path_terminator).map_err(write_error)?;
+            continue;
+        }
+
+        write!(output_stream, "{} {} IN > ", if dry_run { "WOULD REPLACE" } else { "REPLACED" }, file_replacement.replacement_count).map_err(write_error)?;
+        write_colored(output_stream, display_path(&file_replacement.file_path, display_options), Color::Green).map_err(write_error)?;
+        writeln!(output_stream).map_err(write_error)?;
+
+        if dry_run {
+            print_replaced_lines(output_stream, file_replacement).map_err(write_error)?;
+        }
+    }
+
+    if !display_options.quiet {
+        for refused_file in &refused_files {
+            write!(output_stream, "NOT REPLACED({}) - ", refused_file.skip_reason).map_err(write_error)?;
+            write_colored(output_stream, display_path(&refused_file.file_path, display_options), Color::Red).map_err(write_error)?;
+            writeln!(output_stream).map_err(write_error)?;
+        }
+
+        writeln!(output_stream, "{}", "-".repeat(50)).map_err(write_error)?;
+        writeln!(output_stream, "{} {} matches in {} files, {} matched files not replaced.", if dry_run { "Would replace" } else { "Replaced" }, replaced_matches, replaced_files, refused_files.len()).map_err(write_error)?;
+    }
+
+    if failed_writes > 0 {
+        return Err(format!("Couldn't write {} of the files to replace matches in.", failed_writes));
+    }
+
+    Ok(())
+}
+
+// The name that the contents piped in with -content-stdin are reported under.
+const STDIN_NAME:&str = "<stdin>";
+
+// Searches the files that the options describe, or with -content-stdin, the contents piped in.
+fn run_search<F>(search_options:&SearchOptions, content_stdin:bool, on_searched:F) -> Result<SearchResults, String> where F:Fn(&SearchOutcome) + Sync {
+    if content_stdin {
+        search_stream(STDIN_NAME, io::stdin().lock(), search_options, on_searched)
+    } else {
+        perform_search_streaming(search_options, on_searched)
+    }
+}
+
+// Reports an error on stderr and exits with the error code.
+fn exit_with_error(message:&str) -> ! {
+    eprintln!("{}", message);
+    process::exit(EXIT_ERROR);
+}
+
+const HELP_MESSAGE:&str = "
+-spt    | [Necessary] The pattern(s) used to match files. Every argument past this one is considered a pattern, and thus it must be placed after other arguments.
+-spf    | Read patterns from a file, one per line. Blank lines and lines starting with # are ignored. Can be combined with -spt.
+-lit    | Search for this pattern, which is always matched literally, even with -re, e.g. -re -lit src/main.rs -spt 'fn \\w+'. Can be given multiple times.
+          Without -re every pattern is literal anyway, so -lit then behaves like -spt, except it takes a single pattern and can go anywhere.
+-hexpat | Search for the bytes spelled out by this hex string, e.g. -hexpat 7f454c46 for ELF binaries, which is always matched literally. Can be given multiple times.
+          The matched patterns are listed in their hex form. -ci still folds ASCII letters within the bytes.
+-all    | Only consider a file matched if it contains every pattern (AND), rather than any of them (OR, the default).
+-word   | Only count matches that are whole words, i.e. not surrounded by letters, digits or underscores.
+-first  | Stop searching a file at its first match, or with -all at the first match of every pattern. Much faster on large files when you only need to know whether they match, at the cost of -count, -loc and -tally only seeing those matches.
+-maxmatches | Stop searching a file once this many matches were found in it, e.g. for minified files. A file that reaches the cap counts as matched, and is noted as such.
+-timeout-ms | Give up on matching a file after this many milliseconds, and skip it as a match timeout, so a single bad file can't stall a batch run.
+          Checked between chunks, before each regex's pass and between matches. Literal matching is linear and rarely needs this, -re on pathological files might.
+-max-line-length | Skip files holding a line longer than this many bytes, e.g. 2000 to leave out minified JS and CSS, whose huge lines would flood -ctx.
+          Checked while each file is read, which stops reading any file as soon as it's found to have one.
+-overlap | Count overlapping matches too, e.g. aa occurs 3 times in aaaa rather than 2, for -count, -tally and -loc. Which files match doesn't change.
+-min-matches | Only consider a file matched if it contains at least this many matches in total, e.g. to find heavy users of a deprecated API rather than passing mentions.
+          With -all, the file also has to contain every pattern. Can't be combined with -first, which stops counting at the first match.
+-re     | Treat the patterns given to -spt and -spf as regular expressions rather than literal strings, patterns given to -lit stay literal.
+-anchor | Anchor literal patterns that start with ^ to the start of the file, and ones that end with $ to its very end, past any trailing newline, e.g. -anchor -spt '^#!/bin/sh'.
+          A pattern with both has to be the whole file. Patterns given to -lit are never anchored, which is how to search for a literal ^ or $. Has no effect with -re,
+          whose patterns have these anchors anyway.
+-ci     | Match patterns case-insensitively. Literal patterns only fold ASCII letters, any non-ASCII bytes must match exactly; with -re, Unicode case folding is used.
+-dir    | Specifies a directory to perform the operation in, can be given multiple times to search several directories. If not specified, assumes working directory.
+-stdin  | Search exactly the files listed on stdin, one path per line, instead of walking directories, e.g. find . -name '*.rs' | content-search -stdin -spt TODO. Also enabled by -dir -.
+-content-stdin | Search the contents piped to stdin rather than any files, e.g. cat app.log | content-search -content-stdin -spt ERROR. They're reported
+          as a single file named <stdin>. Literal patterns are matched as the input streams in, -re reads it all first. Takes the place of -stdin with -dir -.
+-depth  | Only queue files at most this many directories below each target directory, 0 only queues the files directly inside it.
+-norecurse | Only queue the files directly inside each target directory, the same as -depth 0.
+-max-total-bytes | Stop searching once this many bytes were read across all files, which can be followed by K, M or G. The files left by then are
+          skipped, and how many were searched is reported on stderr. Unlike -mfs, this caps the whole run.
+-mfs    | Do not queue files that exceed this size in bytes, which can be followed by K, M or G, e.g. 10K or 1.5M.
+-nfs    | Do not queue files smaller than this size, which takes the same suffixes as -mfs. Combined with -mfs, this defines an inclusive size window.
+-mtime  | Do not queue files last modified more than this many seconds ago, e.g. 3600 for the last hour.
+-mfq    | Maximum amount of queued files allowed.
+-skip-largest | Skip this percentage of the queued files, the largest ones, e.g. 5 to leave out the 5% of files that would take the longest.
+-j      | Maximum amount of threads used to search through queued files, defaults to one per logical core.
+-readers | Read files with this many threads, one by default, and hand them to separate threads that match them, rather than each thread doing both.
+          Keeps slow disks busy while matching, but every file is read into memory whole. -j doesn't apply then.
+-workers | The amount of threads matching the files read by the -readers threads, one per logical core by default. Either flag enables the split.
+-retry  | Retry opening or reading a file up to this many times, waiting a bit longer each time, before skipping it. Helps with files that are briefly locked.
+-loc    | Print the location of every match as path:line:offset: pattern, the line is - for binary files.
+-exclude-dir | Never descend into directories with this name, e.g. target or node_modules, wherever they are. Can be given multiple times.
+-xglob  | Do not queue files whose path matches this glob, e.g. **/test/**. Can be given multiple times, a file matching any of them is excluded.
+-exclude-file | Do not queue the files listed in this file, one absolute or relative path per line. Blank lines and lines starting with # are ignored.
+          Paths are compared once resolved, so ./a.txt excludes the same file as its absolute path.
+-since-commit | Only queue files that changed since this git ref, according to git diff --name-only, e.g. -since-commit main for what a branch touched.
+          Uncommitted changes count too, untracked files don't. Every target directory has to be inside a git repository.
+-hidden | Queue hidden files, i.e. files whose name or any directory they're in starts with a dot. By default they're skipped, and hidden directories aren't descended into.
+-L      | Follow symlinks, by default symlinked files are skipped and symlinked directories aren't descended into. Symlink cycles are detected and only searched once.
+-gi     | Honor .gitignore, .ignore and global git excludes when queuing files. Ignored files, and the .git directory, are left out of the results entirely.
+-mmap   | Memory map files and match them in place instead of reading them, which is faster on large files. Empty files, and files that can't be mapped, are read normally, -ssk shows why.
+-z      | Decompress files ending in .gz before searching them, the decompressed size is used for -mfs and -nfs. Extension filters also apply to the name without .gz.
+-zip    | Search the files inside archives ending in .zip instead of the archives, each reported as archive.zip::entry/path. -ext, -mfs and -nfs apply to
+          the entries and their decompressed sizes, -xext .zip still leaves the archives out. Entries that fail to decompress are skipped, -ssk lists them.
+-enc    | The encoding of the searched files, one of utf8, utf16le, utf16be or auto. UTF-16 files are decoded before matching, and skipped if they fail to decode. Defaults to utf8, which matches raw bytes.
+          auto detects each file's encoding from its BOM or first few KB, files it can't tell are matched raw and listed with -ssk.
+-text   | Skip files that look binary, i.e. contain a NUL byte within their first 8KB.
+-ctx    | Print every matched line along with this many lines before and after it, like grep -C. Ranges are separated by --, binary files are noted instead.
+          The matches themselves are highlighted when coloring.
+-caret  | With -ctx and without color, mark the matches with ^ on the line below each matched line instead.
+-cache  | Keep the outcome of every searched file in this JSON file, and on later runs don't read the files whose modification time and size haven't changed.
+          The cache starts over whenever the patterns or any other option that affects matching changed.
+-name   | Match the patterns against the path of each file below its -dir instead of its contents, without reading the files. A quick find by substring.
+-name-and-content | Match the patterns against both the path of each file below its -dir and its contents, a file matches if either does.
+-null-data | Treat the files as records separated by NUL bytes, e.g. captured find -print0 output, and list the 0-based indices of the matched records
+          after each matched file. Files holding NUL bytes aren't skipped as binary, and with -all a single record has to contain every pattern.
+-relative | Print the paths relative to the -dir they were found in, rather than with it prepended. With nested -dir roots, the deepest one is stripped.
+          Applies to every output, including -json and -csv.
+-canonical | Print the paths of the searched files fully resolved, i.e. absolute with symlinks, . and .. resolved, however -dir was given. A path
+          that can't be resolved is printed as found, -ssk notes it as a fallback. Composes with -relative, which then strips the resolved -dir.
+-by-dir | List every directory that directly holds matched files instead of the files, with how many of them matched and how many matches they hold
+          in total, the directories with the most matches first. Gives a map of where the matches concentrate in a large tree.
+-group-by-pattern | List every pattern that matched followed by the files it matched in, rather than every file followed by its patterns.
+          The patterns are listed alphabetically, and the files within each one by path.
+-sample | Print a short snippet of the line around the first match after each matched file, a quick look at the context without -ctx.
+-count  | Print the total amount of matches in each matched file as <count>\t<path>, instead of the matched patterns.
+-count-lines | Like -count, but print how many lines in each matched file hold at least one match, like grep -c, or with -null-data how many
+          records do. Binary files are skipped, as with -text.
+-v      | Invert the selection, listing the candidate files that didn't match any pattern instead of the ones that did.
+-ssk    | Show files that were skipped, and the reason behind skipping them.
+-sum    | Show unmatched files (files that met the queue conditions, but didn't match any given pattern).
+-ext    | Only queue files with one of the provided extensions, separated by : or , e.g. .cpp:.hpp or cpp,hpp. Compared case-insensitively.
+-lang   | Only queue files with the extensions of this language, one of c, cpp, csharp, go, java, kotlin, markdown, python, ruby, rust, shell or web,
+          e.g. -lang cpp for .cpp, .hpp, .cc, .h, .cxx and .hxx. Can be given multiple times, and combined with -ext, the extensions of all of them are queued.
+-xext   | Do not queue files with one of the provided extensions, e.g. .log:.tmp. Takes precedence over -ext.
+-all-files | Also queue files with the extensions of well-known binary formats, e.g. .png, .exe or .so, which are skipped by default without being
+          read, and listed with -ssk. Asking for one of them with -ext lets it through regardless.
+-stats  | Print how long the search took, and the throughput in MB/s, after the summary.
+-tally  | Print how many times each pattern matched across every matched file after the summary, busiest patterns first.
+-pattern-id | Print every pattern as #N in the per-file output, N being its position among the patterns, the -lit ones after the others and -hexpat
+          last, with the patterns listed once at the top. Shortens the output when the patterns are long, and keeps it comparable across runs.
+-format-width | Cap the width of the matched patterns column at N characters, cutting longer pattern lists short with an ellipsis, e.g. for narrow
+          terminals. By default the column is as wide as the longest pattern list.
+-head   | Only list the first N files, after sorting them with -sort, so -sort size -reverse -head 10 lists the 10 largest matched files.
+          The search still runs in full, and the summary notes how many files were left out. With -v, the first N unmatched files are listed.
+-head-stop | With -head, stop searching once N files matched, which saves time on huge trees. Files are searched in parallel, so which ones match first
+          depends on timing, and -sort only orders the files that matched by then. The files left are skipped, -ssk lists them.
+-exists | Only find out whether anything matches at all, e.g. for a CI gate, stopping the whole search at the first match. Prints nothing and exits
+          with 0 if something matched and 1 otherwise. With -quiet, the file that matched is printed too.
+-sort   | The order matched files are printed in, one of path, size, matches, mtime or score, ascending except for score. Defaults to path.
+-score  | Score every matched file by the sum of its match counts, each pattern's count multiplied by its weight, and list the highest scores first,
+          printing each before the patterns. Implies -sort score, and the score is also part of -json.
+-weight | How much a single match of a pattern is worth with -score, as pattern=N, e.g. -weight password=5. Can be given more than once, and the
+          patterns that aren't given a weight weigh 1.
+-reverse | Reverse the order of -sort, e.g. -sort matches -reverse lists the noisiest files first.
+-color  | Whether to color the results, one of always, auto or never. Defaults to auto, which only colors when writing to a terminal.
+-quiet  | Only print the matched file paths, one per line, without the parameters, dividers, summary or progress. Composes with -v and -count.
+-progress-interval | Update the progress bars at most once every this many milliseconds, 100 by default. Fewer redraws keep scans of many tiny files
+          from spending their time on the terminal, 0 updates them after every file.
+-verbose | Log the time each phase took, and retried reads, on stderr. Progress bars are hidden.
+-vv     | Also log why each file was skipped, and each fallback. RUST_LOG overrides both, e.g. RUST_LOG=content_search=trace.
+-summary-only | Only print the final counts rather than listing the files, the search still runs in full. Handy for a quick check in CI.
+-0      | End every printed path with a NUL byte instead of a newline, for xargs -0. Implies -quiet.
+-json   | Print the search results as a JSON object instead of the human-readable listing. Progress bars are hidden. Its schema_version field
+          is bumped whenever the layout changes in a way that could break consumers, the -ndjson summary line carries it too.
+-ndjson | Print one JSON object per line instead, for each matched file as soon as it's been searched, then a summary object with the counts.
+          With -v, the lines are for the unmatched files. -sort doesn't apply, as the lines are in the order the files finished in.
+-csv    | Print one path,match_count,patterns row per matched file instead of the human-readable listing, the patterns are separated by ;. Progress bars are hidden.
+-printf | Print every matched file in this format instead, nothing else. The placeholders are %p for the path, %c the match count, %m the patterns joined
+          with ;, %s the size in bytes, %n a newline and %% a literal %, e.g. -printf \"%c %p%n\". Can't be combined with -v.
+-no-header | Leave out the header row of -csv.
+-out    | Write the search results to this file rather than stdout, progress is still shown on stderr. Can be combined with -json.
+-watch  | After the initial search, keep watching the target directories and search every file that's created or modified, printing the files that match
+          as they do. Changes are searched once they've settled for a moment, and every filter still applies. Can't be combined with -json, -ndjson, -csv, -v or -stdin.
+-replace | Replace every match within the matched files with this string, and write the files back. With -re, $1 or ${name} insert a capture group, and $$ a $.
+          Matches of -lit patterns are always replaced literally. Combined with -list, nothing is written, the lines that would change are printed instead.
+          Binary files are left alone, as are gzipped and UTF-16 files, i.e. -z and -enc can't be combined with it.
+-backup | With -replace, copy every file to <path>.bak before writing it.
+-force  | With -replace, also replace matches within files that look binary.
+-list   | Only print the files that would be searched, one per line, without reading them. Useful for tuning -ext, -mfs and the like, no patterns are needed.
+-config | Read the default options from this TOML file, contentsearch.toml in the working directory is read when this isn't given. Flags override the file, and the file overrides the built-in defaults.
+          Its keys are the SearchOptions field names, e.g. file_extensions = [\".rs\"], max_file_size = 1048576 or encoding = \"utf16le\".
+-h      | Displays this help message.
+
+Exit codes: 0 if a file matched (with -v, if a candidate didn't match), 1 if none did, 2 on an error.
+";
+
+// Everything parsed from the command line, on top of the config file.
+struct CommandLine {
+    search_options:SearchOptions,
+    display_options:DisplayOptions,
+    config_path:Option<String>,
+    json_output:bool,
+    ndjson_output:bool,
+    csv_output:bool,
+    csv_header:bool,
+    list_only:bool,
+    read_stdin:bool,
+    content_stdin:bool,
+    output_path:Option<String>,
+    sort_mode:SortMode,
+    reverse_sort:bool,
+    log_level:LevelFilter,
+    benchmark_size:Option<u64>,
+    benchmark_patterns:usize,
+    printf_tokens:Option<Vec<PrintfToken>>,
+    watch:bool,
+    exists:bool,
+    pattern_id:bool,
+    replacement:Option<String>,
+    backup:bool,
+    force_binary:bool,
+    show_help:bool
+}
+
+// The value given after a flag, which is an error when the command line ends before it.
+fn flag_value(flag:&str, next_argument:Option<String>) -> Result<String, String> {
+    match next_argument {
+        Some(next_argument) => Ok(next_argument),
+        None => Err(format!("{} needs a value", flag))
+    }
+}
+
+// The value given after a flag that takes a path or a list. One starting with - is taken for the next flag, so that a left out value
+// doesn't swallow it, e.g. -dir -ext .rs. A lone - is still a value, which -dir reads as stdin, and ./-name reaches a path starting with -.
+fn path_value(flag:&str, next_argument:Option<String>) -> Result<String, String> {
+    let next_argument:String = flag_value(flag, next_argument)?;
+
+    if next_argument.starts_with('-') && next_argument != "-" {
+        return Err(format!("{} needs a value, but was followed by {}", flag, next_argument));
+    }
+
+    Ok(next_argument)
+}
+
+// Parses the command line arguments, returning a description of the first invalid one rather than exiting, so that main decides how to report it.
+// The first argument is the path the program was run as, which is skipped even if it looks like a flag, e.g. a symlink named -dir.
+fn parse_args(arguments:Vec<String>) -> Result<CommandLine, String> {
+    let arguments:Vec<String> = arguments.into_iter().skip(1).collect();
+
+    // The config file is loaded before anything else, so that every flag overrides it.
+    let config_path:Option<String> = match arguments.iter().position(|argument| argument == "-config") {
+        Some(flag_index) => Some(path_value("-config", arguments.get(flag_index + 1).cloned())?),
+        None if Path::new(DEFAULT_CONFIG_PATH).is_file() => Some(String::from(DEFAULT_CONFIG_PATH)),
+        None => None
+    };
+
+    let mut search_options:SearchOptions = match &config_path {
+        Some(config_path) => read_config_file(config_path)?,
+
+        None => SearchOptions::default()
+    };
+
+    // List flags replace the config file's lists rather than adding to them, so they're collected separately.
+    let mut target_directories:Vec<String>  =       Vec::new();
+    let mut file_extensions:Vec<String>     =       Vec::new();
+    let mut excluded_extensions:Vec<String> =       Vec::new();
+    let mut excluded_directories:Vec<String> =      Vec::new();
+    let mut exclude_globs:Vec<String>       =       Vec::new();
+    let mut excluded_paths:Vec<String>      =       Vec::new();
+    let mut patterns:Vec<String>            =       Vec::new();
+    let mut literal_patterns:Vec<String>    =       Vec::new();
+    let mut hex_patterns:Vec<String>        =       Vec::new();
+    let mut pattern_weights:HashMap<String, u64> =  HashMap::new();
+
+    let mut json_output:bool                =       false;
+    let mut ndjson_output:bool              =       false;
+    let mut csv_output:bool                 =       false;
+    let mut csv_header:bool                 =       true;
+    let mut list_only:bool                  =       false;
+    let mut read_stdin:bool                 =       false;
+    let mut content_stdin:bool              =       false;
+    let mut output_path:Option<String>      =       None;
+    let mut sort_mode:SortMode              =       SortMode::Path;
+    let mut reverse_sort:bool               =       false;
+    let mut relative_paths:bool             =       false;
+    let mut log_level:LevelFilter           =       LevelFilter::Warn;
+    let mut benchmark_size:Option<u64>      =       None;
+    let mut benchmark_patterns:usize        =       8;
+    let mut printf_tokens:Option<Vec<PrintfToken>> = None;
+    let mut watch:bool                      =       false;
+    let mut head_stop:bool                  =       false;
+    let mut exists:bool                     =       false;
+    let mut pattern_id:bool                 =       false;
+    let mut replacement:Option<String>      =       None;
+    let mut backup:bool                     =       false;
+    let mut force_binary:bool               =       false;
+    let mut show_help:bool                  =       false;
+
+    let mut display_options:DisplayOptions = DisplayOptions {
+        show_skipped:false,
+        show_unmatched:false,
+        show_locations:false,
+        show_counts:false,
+        count_lines:false,
+        invert_match:false,
+        show_stats:false,
+        show_tally:false,
+        quiet:false,
+        summary_only:false,
+        null_terminated:false,
+        context_lines:None,
+        show_carets:false,
+        show_samples:false,
+        group_by_pattern:false,
+        group_by_directory:false,
+        file_encoding:FileEncoding::Utf8,
+        decompress_gzip:false,
+        relative_roots:Vec::new(),
+        head_limit:None,
+        omitted_files:0,
+        format_width:None,
+        pattern_ids:None,
+        color_choice:ColorChoice::Auto
+    };
+
+    // Create an iterator over the console arguments.
+    let mut argument_iterator = arguments.into_iter();
+
+    // Parse arguments in argument iterator. Flags that take a value consume it from the iterator, so it isn't parsed as an argument itself.
+    while let Some(argument) = argument_iterator.next() {
+        match &argument as &str {
+            "-h" => {
+                show_help = true;
+                break;
+            },
+
+            "-ssk" => {
+                display_options.show_skipped = true;
+            }
+            
+            "-sum" => {
+                display_options.show_unmatched = true;
+            }
+
+            "-re" => {
+                search_options.regex_mode = true;
+            }
+
+            "-ci" => {
+                search_options.case_insensitive = true;
+            }
+
+            "-mmap" => {
+                search_options.memory_map = true;
+            }
+
+            "-z" => {
+                search_options.decompress_gzip = true;
+            }
+
+            "-zip" => {
+                search_options.search_zip = true;
+            }
+
+            "-json" => {
+                json_output = true;
+            }
+
+            "-ndjson" => {
+                ndjson_output = true;
+            }
+
+            "-printf" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                printf_tokens = Some(parse_printf_format(&next_argument)?);
+            }
+
+            "-csv" => {
+                csv_output = true;
+            }
+
+            "-no-header" => {
+                csv_header = false;
+            }
+
+            "-list" => {
+                list_only = true;
+            }
+
+            "-watch" => {
+                watch = true;
+            }
+
+            "-replace" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                replacement = Some(next_argument);
+            }
+
+            "-backup" => {
+                backup = true;
+            }
+
+            "-force" => {
+                force_binary = true;
+            }
+
+            // Left out of the help message, as it's meant for comparing configurations rather than for searching.
+            "-benchmark" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                benchmark_size = match parse_size(&next_argument) {
+                    Ok(0) => return Err(String::from("The provided benchmark corpus size has to be larger than 0.")),
+                    Ok(benchmark_size) => Some(benchmark_size),
+                    Err(error) => return Err(format!("Could not parse the provided benchmark corpus size, error: {}", error))
+                };
+            }
+
+            "-benchmark-patterns" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                benchmark_patterns = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided benchmark pattern count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-loc" => {
+                display_options.show_locations = true;
+            }
+
+            "-text" => {
+                search_options.text_only = true;
+            }
+
+            "-L" => {
+                search_options.follow_symlinks = true;
+            }
+
+            "-gi" => {
+                search_options.respect_ignore = true;
+            }
+
+            "-count" => {
+                display_options.show_counts = true;
+            }
+
+            "-count-lines" => {
+                display_options.show_counts = true;
+                display_options.count_lines = true;
+            }
+
+            "-v" => {
+                display_options.invert_match = true;
+            }
+
+            "-stats" => {
+                display_options.show_stats = true;
+            }
+
+            "-tally" => {
+                display_options.show_tally = true;
+            }
+
+            "-quiet" => {
+                display_options.quiet = true;
+            }
+
+            "-verbose" => {
+                log_level = log_level.max(LevelFilter::Info);
+            }
+
+            "-vv" => {
+                log_level = log_level.max(LevelFilter::Debug);
+            }
+
+            "-summary-only" => {
+                display_options.summary_only = true;
+            }
+
+            // Dividers and the summary would corrupt a NUL-delimited list, so -0 implies -quiet.
+            "-0" => {
+                display_options.quiet = true;
+                display_options.null_terminated = true;
+            }
+
+            "-all" => {
+                search_options.require_all_patterns = true;
+            }
+
+            "-first" => {
+                search_options.stop_at_first = true;
+            }
+
+            "-word" => {
+                search_options.whole_words = true;
+            }
+
+            "-max-total-bytes" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_total_bytes = match parse_size(&next_argument) {
+                    Ok(value) => value,
+                    Err(error) => return Err(format!("Could not parse the provided byte budget, error: {}", error))
+                };
+            }
+
+            "-mfs" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_file_size = match parse_size(&next_argument) {
+                    Ok(value) => value,
+                    Err(error) => return Err(format!("Could not parse the provided maximum file size, error: {}", error))
+                };
+            }
+            
+            "-nfs" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.min_file_size = match parse_size(&next_argument) {
+                    Ok(value) => value,
+                    Err(error) => return Err(format!("Could not parse the provided minimum file size, error: {}", error))
+                };
+            }
+
+            "-maxmatches" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_matches = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum match count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-overlap" => {
+                search_options.overlapping = true;
+            }
+
+            "-min-matches" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.min_matches = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided minimum match count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-timeout-ms" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.match_timeout_ms = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided match timeout ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-progress-interval" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.progress_interval_ms = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided progress interval ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-max-line-length" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_line_length = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum line length ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-mtime" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_age_seconds = match next_argument.parse() {
+                    Ok(value) => Some(value),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum age ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-skip-largest" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.skip_largest_percent = match next_argument.parse() {
+                    Ok(value) if (0.0..=100.0).contains(&value) => value,
+                    Ok(_) => return Err(format!("The provided percentage of largest files to skip ({}) has to be between 0 and 100.", next_argument)),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided percentage of largest files to skip ({}) into a number, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-head" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                display_options.head_limit = match next_argument.parse() {
+                    Ok(0) => return Err(String::from("The provided -head count has to be larger than 0.")),
+                    Ok(value) => Some(value),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided -head count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-format-width" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                display_options.format_width = match next_argument.parse() {
+                    Ok(0) => return Err(String::from("The provided -format-width has to be larger than 0.")),
+                    Ok(value) => Some(value),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided -format-width ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-head-stop" => {
+                head_stop = true;
+            }
+
+            "-exists" => {
+                exists = true;
+            }
+
+            "-pattern-id" => {
+                pattern_id = true;
+            }
+
+            "-mfq" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_files = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum queued file count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-depth" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_depth = match next_argument.parse() {
+                    Ok(value) => Some(value),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum depth ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-since-commit" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.changed_since = Some(next_argument);
+            }
+
+            "-cache" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                search_options.cache_path = Some(next_argument);
+            }
+
+            "-name" => {
+                search_options.match_target = MatchTarget::Paths;
+            }
+
+            "-name-and-content" => {
+                search_options.match_target = MatchTarget::PathsAndContents;
+            }
+
+            "-null-data" => {
+                search_options.null_data = true;
+            }
+
+            "-relative" => {
+                relative_paths = true;
+            }
+
+            "-canonical" => {
+                search_options.canonical_paths = true;
+            }
+
+            "-by-dir" => {
+                display_options.group_by_directory = true;
+            }
+
+            "-group-by-pattern" => {
+                display_options.group_by_pattern = true;
+            }
+
+            "-sample" => {
+                search_options.capture_sample = true;
+                display_options.show_samples = true;
+            }
+
+            "-anchor" => {
+                search_options.anchor_patterns = true;
+            }
+
+            "-caret" => {
+                display_options.show_carets = true;
+            }
+
+            "-norecurse" => {
+                search_options.max_depth = Some(0);
+            }
+
+            "-ctx" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                display_options.context_lines = match next_argument.parse() {
+                    Ok(value) => Some(value),
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided context line count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-j" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.max_threads = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided maximum thread count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-readers" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.reader_threads = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided reader thread count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-workers" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.worker_threads = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided worker thread count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-retry" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.retry_count = match next_argument.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the provided retry count ({}) into an integer, error: {}", next_argument, error));
+                    }
+                };
+            }
+
+            "-color" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                display_options.color_choice = match &next_argument as &str {
+                    "always" => ColorChoice::Always,
+                    "auto" => ColorChoice::Auto,
+                    "never" => ColorChoice::Never,
+                    _ => return Err(format!("Unknown color mode ({}), expected always, auto or never.", next_argument))
+                };
+            }
+
+            "-enc" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                search_options.encoding = match &next_argument as &str {
+                    "utf8" => FileEncoding::Utf8,
+                    "utf16le" => FileEncoding::Utf16Le,
+                    "utf16be" => FileEncoding::Utf16Be,
+                    "auto" => FileEncoding::Auto,
+                    _ => return Err(format!("Unknown encoding ({}), expected utf8, utf16le, utf16be or auto.", next_argument))
+                };
+            }
+
+            "-sort" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                sort_mode = match &next_argument as &str {
+                    "path" => SortMode::Path,
+                    "size" => SortMode::Size,
+                    "matches" => SortMode::Matches,
+                    "mtime" => SortMode::Mtime,
+                    "score" => {
+                        search_options.score_matches = true;
+                        SortMode::Score
+                    }
+                    _ => return Err(format!("Unknown sort mode ({}), expected path, size, matches, mtime or score.", next_argument))
+                };
+            }
+
+            "-score" => {
+                search_options.score_matches = true;
+                sort_mode = SortMode::Score;
+            }
+
+            "-weight" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                let (weighted_pattern, weight) = match next_argument.rsplit_once('=') {
+                    Some(weighted_pattern) => weighted_pattern,
+                    None => return Err(format!("The weight ({}) isn't in the form pattern=N.", next_argument))
+                };
+
+                let weight:u64 = match weight.parse() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        return Err(format!("Could not convert the weight of the pattern ({}) into an integer, error: {}", weighted_pattern, error));
+                    }
+                };
+
+                pattern_weights.insert(String::from(weighted_pattern), weight);
+            }
+
+            "-reverse" => {
+                reverse_sort = true;
+            }
+
+            "-dir" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                if next_argument == "-" {
+                    read_stdin = true;
+                } else {
+                    target_directories.push(next_argument);
+                }
+            }
+
+            "-hidden" => {
+                search_options.include_hidden = true;
+            }
+
+            "-stdin" => {
+                read_stdin = true;
+            }
+
+            "-content-stdin" => {
+                content_stdin = true;
+            }
+
+            "-ext" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                file_extensions.extend(parse_extension_list(&next_argument));
+            }
+
+            "-lang" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                file_extensions.extend(language_extensions(&next_argument)?);
+            }
+
+            "-xext" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                excluded_extensions.extend(parse_extension_list(&next_argument));
+            }
+
+            "-all-files" => {
+                search_options.skip_binary_extensions = false;
+            }
+
+            "-spf" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                match read_pattern_file(&next_argument) {
+                    Ok(file_patterns) => patterns.extend(file_patterns),
+                    Err(error) => return Err(error)
+                };
+            }
+
+            "-out" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                output_path = Some(next_argument);
+            }
+
+            // Already loaded before parsing, only its value needs to be skipped.
+            "-config" => {
+                path_value(&argument, argument_iterator.next())?;
+            }
+
+            "-exclude-dir" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                excluded_directories.push(next_argument);
+            }
+
+            "-xglob" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                exclude_globs.push(next_argument);
+            }
+
+            "-exclude-file" => {
+                let next_argument:String = path_value(&argument, argument_iterator.next())?;
+                excluded_paths.extend(read_exclude_file(&next_argument)?);
+            }
+
+            "-lit" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                literal_patterns.push(next_argument);
+            }
+
+            "-hexpat" => {
+                let next_argument:String = flag_value(&argument, argument_iterator.next())?;
+                decode_hex_pattern(&next_argument)?;
+                hex_patterns.push(next_argument);
+            }
+
+            "-spt" => {
+                patterns.extend(argument_iterator.by_ref());
+            }
+
+            _ => {
+                continue;
+            }
+        };
+    }
+
+    if printf_tokens.is_some() && display_options.invert_match {
+        return Err(String::from("-printf can't be combined with -v, since its placeholders describe matched files."));
+    }
+
+    if display_options.head_limit.is_some() && ndjson_output {
+        return Err(String::from("-head can't be combined with -ndjson, whose lines are written as the files are searched, before they could be sorted and truncated."));
+    }
+
+    // Line numbers mean nothing in binary files, so they're skipped rather than counted as holding no lines.
+    if display_options.count_lines {
+        search_options.text_only = true;
+    }
+
+    if display_options.group_by_directory && display_options.group_by_pattern {
+        return Err(String::from("-by-dir can't be combined with -group-by-pattern, they're different ways of listing the same files."));
+    }
+
+    if head_stop {
+        match display_options.head_limit {
+            Some(_) if display_options.invert_match => return Err(String::from("-head-stop can't be combined with -v, since it stops once enough files matched rather than didn't.")),
+            Some(head_limit) => search_options.max_matched_files = head_limit,
+            None => return Err(String::from("-head-stop only applies to -head."))
+        }
+    }
+
+    // Only whether anything matched at all matters, so the search stops at the first match of the first file to match.
+    if exists {
+        if display_options.invert_match || json_output || ndjson_output || csv_output || printf_tokens.is_some() || output_path.is_some() || display_options.head_limit.is_some()
+            || watch || replacement.is_some() || list_only {
+            return Err(String::from("-exists can't be combined with -v, -json, -ndjson, -csv, -printf, -out, -head, -watch, -replace or -list, it only answers through the exit code."));
+        }
+
+        search_options.max_matched_files = 1;
+        search_options.stop_at_first = true;
+    }
+
+    if watch && (json_output || ndjson_output || csv_output) {
+        return Err(String::from("-watch can't be combined with -json, -ndjson or -csv, since it keeps printing matches rather than ending with a complete report."));
+    }
+
+    // Piped contents take the place of the path list that -dir - would otherwise read from stdin.
+    if content_stdin {
+        read_stdin = false;
+
+        if watch || replacement.is_some() || list_only || display_options.context_lines.is_some() || search_options.match_target != MatchTarget::Contents {
+            return Err(String::from("-content-stdin can't be combined with -watch, -replace, -list, -ctx, -name or -name-and-content, which all need files to read or reread."));
+        }
+    }
+
+    if watch && (display_options.invert_match || read_stdin) {
+        return Err(String::from("-watch can't be combined with -v or -stdin, since it only searches the files that change within the target directories."));
+    }
+
+    if replacement.is_some() && (json_output || ndjson_output || csv_output || printf_tokens.is_some() || watch) {
+        return Err(String::from("-replace can't be combined with -json, -ndjson, -csv, -printf or -watch, it reports the replacements on its own."));
+    }
+
+    if replacement.is_some() && (display_options.invert_match || search_options.match_target != MatchTarget::Contents) {
+        return Err(String::from("-replace can't be combined with -v, -name or -name-and-content, since only the matches within the contents of matched files can be replaced."));
+    }
+
+    if replacement.is_some() && (search_options.decompress_gzip || search_options.encoding != FileEncoding::Utf8) {
+        return Err(String::from("-replace can't be combined with -z or -enc, since files are rewritten as raw bytes."));
+    }
+
+    // Archive entries have no path of their own to read their lines back from, or to write a replacement to.
+    if search_options.search_zip && (replacement.is_some() || display_options.context_lines.is_some()) {
+        return Err(String::from("-zip can't be combined with -replace or -ctx, since archive entries can't be reread or rewritten in place."));
+    }
+
+    if replacement.is_none() && (backup || force_binary) {
+        return Err(String::from("-backup and -force only apply to -replace."));
+    }
+
+    if search_options.stop_at_first && search_options.null_data && search_options.require_all_patterns {
+        return Err(String::from("-first can't be combined with -null-data and -all, since the record holding every pattern could come after the first matches."));
+    }
+
+    if search_options.stop_at_first && search_options.min_matches > 1 {
+        return Err(String::from("-first can't be combined with -min-matches, since it stops counting at the first match."));
+    }
+
+    if !target_directories.is_empty() {
+        search_options.directories = target_directories;
+    }
+
+    // The canonical paths would never start with a root that wasn't resolved in the same way.
+    if relative_paths && search_options.canonical_paths {
+        display_options.relative_roots = search_options.directories.iter().map(|directory| fs::canonicalize(directory).ok().and_then(|canonical_path| canonical_path.to_str().map(String::from)).unwrap_or_else(|| directory.clone())).collect();
+    } else if relative_paths {
+        display_options.relative_roots = search_options.directories.clone();
+    }
+
+    if !file_extensions.is_empty() {
+        search_options.file_extensions = file_extensions;
+    }
+
+    if !excluded_extensions.is_empty() {
+        search_options.excluded_extensions = excluded_extensions;
+    }
+
+    if !excluded_directories.is_empty() {
+        search_options.excluded_directories = excluded_directories;
+    }
+
+    if !exclude_globs.is_empty() {
+        search_options.exclude_globs = exclude_globs;
+    }
+
+    if !excluded_paths.is_empty() {
+        search_options.excluded_paths = excluded_paths;
+    }
+
+    if !patterns.is_empty() {
+        search_options.patterns = patterns;
+    }
+
+    if !literal_patterns.is_empty() {
+        search_options.literal_patterns = literal_patterns;
+    }
+
+    if !hex_patterns.is_empty() {
+        search_options.hex_patterns = hex_patterns;
+    }
+
+    if !pattern_weights.is_empty() {
+        search_options.pattern_weights = pattern_weights;
+    }
+
+    if !search_options.pattern_weights.is_empty() && !search_options.score_matches {
+        return Err(String::from("-weight requires -score."));
+    }
+
+    // Weights are looked up by the pattern as it's reported, so one that doesn't name a pattern would silently weigh nothing.
+    for weighted_pattern in search_options.pattern_weights.keys() {
+        if !search_options.patterns.contains(weighted_pattern) && !search_options.literal_patterns.contains(weighted_pattern) && !search_options.hex_patterns.contains(weighted_pattern) {
+            return Err(format!("The weighted pattern ({}) isn't one of the patterns being searched for.", weighted_pattern));
+        }
+    }
+
+    Ok(CommandLine {
+        search_options,
+        display_options,
+        config_path,
+        json_output,
+        ndjson_output,
+        csv_output,
+        csv_header,
+        list_only,
+        read_stdin,
+        content_stdin,
+        output_path,
+        sort_mode,
+        reverse_sort,
+        log_level,
+        benchmark_size,
+        benchmark_patterns,
+        printf_tokens,
+        watch,
+        exists,
+        pattern_id,
+        replacement,
+        backup,
+        force_binary,
+        show_help
+    })
+}
+
+fn main() {
+    let command_line:CommandLine = match parse_args(env::args().collect()) {
+        Ok(command_line) => command_line,
+        Err(error) => exit_with_error(&error)
+    };
+
+    if command_line.show_help {
+        println!("{}", HELP_MESSAGE);
+        return;
+    }
+
+    let CommandLine { mut search_options, mut display_options, config_path, json_output, ndjson_output, csv_output, csv_header, list_only, read_stdin, content_stdin, output_path, sort_mode, reverse_sort, log_level, benchmark_size, benchmark_patterns, printf_tokens, watch, exists, pattern_id, replacement, backup, force_binary, .. } = command_line;
+
+    init_logger(log_level);
+    warn_about_whitespace(&search_options.file_extensions, "-ext");
+    warn_about_whitespace(&search_options.excluded_extensions, "-xext");
+    dedupe_patterns(&mut search_options);
+
+    // Numbered once the duplicates are gone, so that the numbers line up with the indices the matcher reports.
+    if pattern_id {
+        display_options.pattern_ids = Some(search_patterns(&search_options).cloned().collect());
+    }
+
+    if read_stdin {
+        match read_stdin_paths() {
+            Ok(input_files) => search_options.input_files = Some(input_files),
+            Err(error) => exit_with_error(&error)
+        };
+    }
+
+    display_options.file_encoding = search_options.encoding;
+    display_options.decompress_gzip = search_options.decompress_gzip;
+
+    // Only color automatically when writing to a terminal, so that piped output never contains escape codes.
+    if display_options.color_choice == ColorChoice::Auto && !io::stdout().is_terminal() {
+        display_options.color_choice = ColorChoice::Never;
+    }
+
+    let machine_output:bool = json_output || ndjson_output || csv_output || printf_tokens.is_some();
+
+    // Progress bars would only garble redirected output, or interleave with a JSON report or the log events beyond warnings.
+    search_options.show_progress = !machine_output && !display_options.quiet && !exists && io::stdout().is_terminal() && io::stderr().is_terminal()
+        && log::max_level() <= LevelFilter::Warn;
+
+    // Unless something reports the matches themselves, only which patterns each file contains matters, so repeated matches needn't be searched for.
+    search_options.presence_only = !machine_output && !display_options.show_counts && !display_options.show_locations && display_options.context_lines.is_none()
+        && !display_options.show_tally && !display_options.group_by_directory && sort_mode != SortMode::Matches && !search_options.score_matches;
+
+    if let Some(benchmark_size) = benchmark_size {
+        if let Err(error) = run_benchmark(search_options, benchmark_size, benchmark_patterns) {
+            exit_with_error(&error);
+        }
+
+        return;
+    }
+
+    // -list only runs the queuing phase, so it doesn't need any patterns. With -replace, it's a dry run that does search instead.
+    if list_only && replacement.is_none() {
+        let file_queue:FileQueue = match queue_files(&search_options) {
+            Ok(file_queue) => file_queue,
+            Err(error) => exit_with_error(&format!("queue_files Returned an error: {:?}", error))
+        };
+
+        for queued_file in &file_queue.queued_files {
+            println!("{}", queued_file.file_path);
+        }
+
+        return;
+    }
+
+    if !search_options.patterns.is_empty() || !search_options.literal_patterns.is_empty() || !search_options.hex_patterns.is_empty() {
+        // Nothing is printed besides, with -quiet, the file that matched, or the few that did at the same time in parallel.
+        if exists {
+            let search_results:SearchResults = match run_search(&search_options, content_stdin, |_| ()) {
+                Ok(search_results) => search_results,
+                Err(error) => exit_with_error(&format!("perform_search Returned an error: {:?}", error))
+            };
+
+            if display_options.quiet {
+                let mut output_stream = StandardStream::stdout(display_options.color_choice);
+
+                if let Err(error) = print_quiet_results(&mut output_stream, &search_results, &display_options) {
+                    exit_with_error(&format!("Couldn't write the search results, error: {:?}", error));
+                }
+            }
+
+            process::exit(exit_code_for(&search_results, &display_options));
+        }
+
+        if !machine_output && !display_options.quiet {
+            println!("Performing content search with the following parameters.");
+            println!("\n{}", "-".repeat(50));
+
+            if let Some(config_path) = &config_path {
+                println!("Config File: {}", config_path);
+            }
+
+            println!("Search Patterns: {:?}", search_options.patterns);
+            println!("Literal Patterns: {:?}", search_options.literal_patterns);
+            println!("Hex Patterns: {:?}", search_options.hex_patterns);
+            match &search_options.input_files {
+                Some(input_files) => println!("Target Files: {} read from stdin", input_files.len()),
+                None if content_stdin => println!("Target: The contents piped to stdin, as {}", STDIN_NAME),
+                None => println!("Target Dirs: {:?}", search_options.directories)
+            };
+            println!("File Extensions: {:?}", search_options.file_extensions);
+            println!("Excluded Extensions: {:?}", search_options.excluded_extensions);
+            println!("Skip Binary Extensions: {}", search_options.skip_binary_extensions);
+            println!("Excluded Directories: {:?}", search_options.excluded_directories);
+            println!("Exclude Globs: {:?}", search_options.exclude_globs);
+            println!("Excluded Paths: {}", search_options.excluded_paths.len());
+            println!("Max File Size: {}", search_options.max_file_size);
+            println!("Min File Size: {}", search_options.min_file_size);
+            println!("Max Line Length: {}", search_options.max_line_length);
+            println!("Max Age (seconds): {:?}", search_options.max_age_seconds);
+            println!("Max Queued Files: {}", search_options.max_files);
+            println!("Max Total Bytes: {}", search_options.max_total_bytes);
+            println!("Max Matched Files: {}", search_options.max_matched_files);
+            println!("Head: {:?}", display_options.head_limit);
+            println!("Skip Largest (%): {}", search_options.skip_largest_percent);
+            println!("Max Depth: {:?}", search_options.max_depth);
+            println!("Include Hidden: {}", search_options.include_hidden);
+            println!("Follow Symlinks: {}", search_options.follow_symlinks);
+            println!("Changed Since: {:?}", search_options.changed_since);
+            println!("Max Threads: {}", search_options.max_threads);
+            println!("Reader / Worker Threads: {} / {}", search_options.reader_threads, search_options.worker_threads);
+            println!("Retries: {}", search_options.retry_count);
+            println!("Match Timeout (ms): {}", search_options.match_timeout_ms);
+            println!("Progress Interval (ms): {}", search_options.progress_interval_ms);
+            println!("Regex Mode: {}", search_options.regex_mode);
+            println!("Anchor Patterns: {}", search_options.anchor_patterns);
+            println!("Case Insensitive: {}", search_options.case_insensitive);
+            println!("Require All Patterns: {}", search_options.require_all_patterns);
+            println!("Max Matches Per File: {}", search_options.max_matches);
+            println!("Min Matches Per File: {}", search_options.min_matches);
+            println!("Overlapping Matches: {}", search_options.overlapping);
+            println!("Score Matches: {}", search_options.score_matches);
+            println!("Pattern Weights: {:?}", search_options.pattern_weights);
+            println!("Encoding: {:?}", search_options.encoding);
+            println!("Decompress Gzip: {}", search_options.decompress_gzip);
+            println!("Search Zip Archives: {}", search_options.search_zip);
+            println!("Canonical Paths: {}", search_options.canonical_paths);
+            println!("NUL Separated Records: {}", search_options.null_data);
+            println!("Match Target: {:?}", search_options.match_target);
+            println!("Cache File: {:?}", search_options.cache_path);
+            println!("Watch: {}", watch);
+            println!("Replacement: {:?}", replacement);
+            println!("{}", "-".repeat(50));
+        }
+
+        // The lines are written from the searching threads, so this needs a stream that can be sent between them, unlike output_stream.
+        if ndjson_output {
+            let raw_stream:Box<dyn Write + Send> = match &output_path {
+                Some(output_path) => match File::create(output_path) {
+                    Ok(output_file) => Box::new(output_file),
+                    Err(error) => exit_with_error(&format!("Couldn't create the output file @ {}, error: {}", output_path, error))
+                },
+
+                None => Box::new(io::stdout())
+            };
+
+            let ndjson_stream:Mutex<NdjsonStream> = Mutex::new(NdjsonStream { output_stream:BufWriter::new(raw_stream), write_result:Ok(()) });
+
+            let search_results:SearchResults = match run_search(&search_options, content_stdin, |search_outcome| write_ndjson_outcome(&ndjson_stream, search_outcome, &display_options)) {
+                Ok(search_results) => search_results,
+                Err(error) => exit_with_error(&format!("perform_search Returned an error: {:?}", error))
+            };
+
+            let mut ndjson_stream:NdjsonStream = ndjson_stream.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            ndjson_stream.write_record(&NdjsonRecord::Summary {
+                schema_version:JSON_SCHEMA_VERSION,
+                inverted:display_options.invert_match,
+                matched_count:search_results.matched_files.len(),
+                skipped_count:search_results.skipped_files.len(),
+                unmatched_count:search_results.unmatched_files.len()
+            });
+
+            if let Err(error) = ndjson_stream.write_result {
+                exit_with_error(&format!("Couldn't write the search results, error: {:?}", error));
+            }
+
+            process::exit(exit_code_for(&search_results, &display_options));
+        }
+
+        // The report goes to the -out file when one is given, which is never colored, and to stdout otherwise.
+        let mut output_stream:Box<dyn WriteColor> = match &output_path {
+            Some(output_path) => match File::create(output_path) {
+                Ok(output_file) => Box::new(NoColor::new(BufWriter::new(output_file))),
+                Err(error) => exit_with_error(&format!("Couldn't create the output file @ {}, error: {}", output_path, error))
+            },
+
+            None => Box::new(StandardStream::stdout(display_options.color_choice))
+        };
+
+        let mut search_results:SearchResults = match run_search(&search_options, content_stdin, |_| ()) {
+            Ok(search_results) => search_results,
+            Err(error) => exit_with_error(&format!("perform_search Returned an error: {:?}", error))
+        };
+
+        sort_matched_files(&mut search_results.matched_files, sort_mode, reverse_sort);
+        truncate_results(&mut search_results, &mut display_options);
+
+        if let Some(replacement) = &replacement {
+            let replace_result:Result<(), String> = run_replacements(&mut *output_stream, &search_results, &search_options, &display_options, replacement, backup, force_binary, list_only);
+
+            if let Err(error) = replace_result.and_then(|_| output_stream.flush().map_err(|error| format!("Couldn't write the replacements, error: {:?}", error))) {
+                exit_with_error(&error);
+            }
+
+            process::exit(exit_code_for(&search_results, &display_options));
+        }
+
+        let print_result:io::Result<()> = if json_output {
+            strip_relative_roots(&mut search_results, &display_options);
+            print_json_results(&mut *output_stream, &search_results, &display_options)
+        } else if csv_output {
+            print_csv_results(&mut *output_stream, &search_results, &display_options, csv_header)
+        } else if let Some(printf_tokens) = &printf_tokens {
+            print_printf_results(&mut *output_stream, &search_results, &display_options, printf_tokens)
+        } else if display_options.quiet {
+            print_quiet_results(&mut *output_stream, &search_results, &display_options)
+        } else if display_options.summary_only {
+            print_summary(&mut *output_stream, &search_results, &display_options)
+        } else {
+            print_results(&mut *output_stream, &search_results, &display_options)
+        };
+
+        if let Err(error) = print_result.and_then(|_| output_stream.flush()) {
+            exit_with_error(&format!("Couldn't write the search results, error: {:?}", error));
+        }
+
+        if watch {
+            // The program's own output and cache are usually inside the watched directories too, and writing them would trigger another search.
+            let ignored_paths:Vec<String> = output_path.iter().chain(search_options.cache_path.iter()).cloned().collect();
+
+            if let Err(error) = run_watch(search_options, &mut *output_stream, &display_options, printf_tokens.as_deref(), &ignored_paths) {
+                exit_with_error(&error);
+            }
+        }
+
+        process::exit(exit_code_for(&search_results, &display_options));
+    } else {
+        exit_with_error("Please specify at least one search pattern.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Log, Metadata, Record};
+
+    // The command line as main would receive it, run as content-search.
+    fn command_line(arguments:&[&str]) -> Vec<String> {
+        std::iter::once("content-search").chain(arguments.iter().copied()).map(String::from).collect()
+    }
+
+    #[test]
+    fn invalid_size_is_an_error() {
+        let parse_error:String = match parse_args(command_line(&["-mfs", "abc", "-spt", "foo"])) {
+            Ok(_) => panic!("-mfs abc was accepted"),
+            Err(parse_error) => parse_error
+        };
+
+        assert_eq!(parse_error, "Could not parse the provided maximum file size, error: Invalid size (abc), expected a number of bytes optionally followed by K, M or G, e.g. 10K or 1.5M");
+    }
+
+    #[test]
+    fn program_path_is_never_parsed_as_a_flag() {
+        let arguments:Vec<String> = vec![String::from("-dir"), String::from("-spt"), String::from("foo")];
+
+        let parsed_line:CommandLine = match parse_args(arguments) {
+            Ok(parsed_line) => parsed_line,
+            Err(parse_error) => panic!("{}", parse_error)
+        };
+
+        assert_eq!(parsed_line.search_options.directories, vec!["."]);
+        assert_eq!(parsed_line.search_options.patterns, vec!["foo"]);
+    }
+
+    // Keeps every warning that's logged, so that tests can check for them. The logger is global, so the tests that run in parallel only
+    // look for the warnings they caused.
+    struct WarningLog {
+        warnings:Mutex<Vec<String>>
+    }
+
+    impl Log for WarningLog {
+        fn enabled(&self, metadata:&Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record:&Record) {
+            if self.enabled(record.metadata()) {
+                if let Ok(mut warnings) = self.warnings.lock() {
+                    warnings.push(record.args().to_string());
+                }
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static WARNING_LOG:WarningLog = WarningLog { warnings:Mutex::new(Vec::new()) };
+
+    fn logged_warnings() -> Vec<String> {
+        // Only the first call installs it, every later one finds it already there.
+        if log::set_logger(&WARNING_LOG).is_ok() {
+            log::set_max_level(LevelFilter::Warn);
+        }
+
+        WARNING_LOG.warnings.lock().map(|warnings| warnings.clone()).unwrap_or_default()
+    }
+
+    #[test]
+    fn repeated_patterns_are_searched_once() {
+        logged_warnings();
+
+        let mut parsed_line:CommandLine = match parse_args(command_line(&["-spt", "foo", "foo", "bar"])) {
+            Ok(parsed_line) => parsed_line,
+            Err(parse_error) => panic!("{}", parse_error)
+        };
+
+        dedupe_patterns(&mut parsed_line.search_options);
+
+        assert_eq!(parsed_line.search_options.patterns, vec!["foo", "bar"]);
+        assert_eq!(logged_warnings().iter().filter(|warning| warning.contains("(foo) was given more than once")).count(), 1);
+    }
+
+    #[test]
+    fn adjacent_value_flags_each_take_their_own_value() {
+        let parsed_line:CommandLine = match parse_args(command_line(&["-mfq", "3", "-mfs", "10", "-dir", "x", "-spt", "foo"])) {
+            Ok(parsed_line) => parsed_line,
+            Err(parse_error) => panic!("{}", parse_error)
+        };
+
+        assert_eq!(parsed_line.search_options.max_files, 3);
+        assert_eq!(parsed_line.search_options.max_file_size, 10);
+        assert_eq!(parsed_line.search_options.directories, vec!["x"]);
+        assert_eq!(parsed_line.search_options.patterns, vec!["foo"]);
+
+        let parse_error = |arguments:&[&str]| match parse_args(command_line(arguments)) {
+            Ok(_) => panic!("{:?} was accepted", arguments),
+            Err(parse_error) => parse_error
+        };
+
+        assert_eq!(parse_error(&["-mfq", "3", "-mfs"]), "-mfs needs a value");
+        assert_eq!(parse_error(&["-spf"]), "-spf needs a value");
+        assert_eq!(parse_error(&["-dir", "-ext", ".rs", "-spt", "foo"]), "-dir needs a value, but was followed by -ext");
+        assert!(parse_args(command_line(&["-dir", "-", "-spt", "foo"])).is_ok());
+    }
+
+    #[test]
+    fn json_reports_lead_with_the_schema_version() {
+        let no_matches:Vec<MatchedFile> = Vec::new();
+        let no_skips:Vec<SkippedFile> = Vec::new();
+        let no_unmatched:Vec<String> = Vec::new();
+
+        let json_report = JsonReport {
+            schema_version:JSON_SCHEMA_VERSION,
+            inverted:false,
+            matched_count:0,
+            skipped_count:0,
+            unmatched_count:0,
+            matched_files:Some(&no_matches),
+            skipped_files:&no_skips,
+            unmatched_files:&no_unmatched
+        };
+
+        let report_json:String = serde_json::to_string(&json_report).unwrap();
+        let parsed_report:serde_json::Value = serde_json::from_str(&report_json).unwrap();
+
+        assert!(report_json.starts_with("{\"schema_version\":2,"), "{}", report_json);
+        assert_eq!(parsed_report["schema_version"], 2);
+    }
+
+    // The type tag always comes first in an NDJSON line, the version comes right after it.
+    #[test]
+    fn ndjson_summaries_lead_with_the_schema_version() {
+        let summary_record = NdjsonRecord::Summary { schema_version:JSON_SCHEMA_VERSION, inverted:false, matched_count:1, skipped_count:0, unmatched_count:0 };
+
+        let summary_json:String = serde_json::to_string(&summary_record).unwrap();
+        let parsed_summary:serde_json::Value = serde_json::from_str(&summary_json).unwrap();
+
+        assert!(summary_json.starts_with("{\"type\":\"summary\",\"schema_version\":2,"), "{}", summary_json);
+        assert_eq!(parsed_summary["schema_version"], 2);
+    }
+}